@@ -3,11 +3,26 @@
 mod state;
 
 use battleship_game::{
-    apply_attack, apply_sunk_padding, empty_enemy_view, set_enemy_view_cell, validate_and_build_board, BattleshipAbi,
-    CrossChainMessage, EnemyCell, GameState, MatchmakingPlayer, Operation, PlayerInfo, RevealInfo, Room, RoomStatus,
+    accept_timeout_claim, advance_turn, agree_to_end, apply_attack, apply_salvo,
+    apply_sunk_padding, apply_weapon, assign_teams, attacker_keeps_turn, authenticate_orchestrator,
+    authenticate_room_host, authenticate_room_player, authenticate_sender, average_wait_secs,
+    bot_can_fire, chain_seed, check_join_code, choose_bot_salvo, choose_bot_shot, claim_timeout,
+    eliminate_player, empty_enemy_view, end_game, finish_if_last_standing, forfeit_winner,
+    hash_join_code, is_open_for_listing, join_rejection, match_settings, mine_blast_target,
+    new_tournament, next_series_game, preferences_compatible, random_board_placements,
+    record_match_wait, record_ship_sunk, record_shot, record_tournament_result, record_weapon_use,
+    register_tournament_player, remove_player, resolve_target, room_listing, room_started,
+    salvo_shot_count, save_layout, series_first_attacker, stake_payouts, start_tournament,
+    take_stake, take_stale_waiters, teammate_of, validate_and_build_board, validate_preferences,
+    validate_room_settings, weapon_keeps_turn, weapons_remaining, AttackOutcome, BattleshipAbi,
+    BattleshipParameters, BotConfig, Coord, CrossChainMessage, EnemyBoardView, EnemyCell, GameMode,
+    GameState, MatchmakingPlayer, MineEffect, Operation, PlayerInfo, Proposal, PublishedRoom,
+    QueueStatus, RevealInfo, Room, RoomSettings, RoomStatus, SavedLayout, ShapePlacementInput,
+    ShipPlacementInput, ShotResult, Stake, Tournament, TournamentMatch, TournamentMatchRef,
+    TournamentStatus, WeaponShot, BOARD_SIZE, STANDARD_FLEET,
 };
 use linera_sdk::{
-    linera_base_types::{ChainId, WithContractAbi},
+    linera_base_types::{Account, AccountOwner, Amount, ChainId, WithContractAbi},
     views::{RootView, View},
     Contract, ContractRuntime,
 };
@@ -30,44 +45,915 @@ impl BattleshipContract {
         self.state.room.get().clone().expect("Room not found")
     }
 
-    fn set_room(&mut self, room: Room) {
+    fn set_room(&mut self, mut room: Room) {
+        self.report_tournament_result(&room);
+        if room.game_state == GameState::Ended && room.status == RoomStatus::Active {
+            // A game of the series is over; the room goes straight on to the next one.
+            next_series_game(&mut room);
+        }
+        self.settle_stakes(&mut room);
+        room.last_activity_at = self.runtime.system_time().micros();
+        let previous = self.state.room.get().clone();
         self.state.room.set(Some(room));
+        self.clear_boards_for_next_game(previous.as_ref());
+    }
+
+    /// Drops the boards of the previous game once the room has moved on to the next game of its series.
+    fn clear_boards_for_next_game(&mut self, previous: Option<&Room>) {
+        let Some(room) = self.state.room.get().clone() else {
+            return;
+        };
+        let Some(previous) = previous else {
+            return;
+        };
+        if previous.room_id != room.room_id || previous.game_number >= room.game_number {
+            return;
+        }
+        self.state.board.set(None);
+        self.state.teammate_board.set(None);
+        self.state.enemy_views.set(Vec::new());
+        self.ensure_enemy_views_created();
+        let score = room
+            .players
+            .iter()
+            .map(|p| format!("{} {}", p.name, p.series_wins))
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.state.last_notification.set(Some(format!(
+            "Game {} of {} ({}): place your fleet",
+            room.game_number, room.settings.best_of, score
+        )));
+    }
+
+    /// Reports the winner of the tournament match hosted here once its room ends. Rooms that end
+    /// without a winner are left for the organizer to decide.
+    fn report_tournament_result(&mut self, room: &Room) {
+        let Some(current) = self.state.tournament_match.get().clone() else {
+            return;
+        };
+        if current.room_id != room.room_id || room.status == RoomStatus::Active {
+            return;
+        }
+        self.state.tournament_match.set(None);
+        let winner = room
+            .winner_chain_id
+            .as_deref()
+            .and_then(|c| c.parse::<ChainId>().ok());
+        if let (Some(winner_chain_id), Ok(organizer)) =
+            (winner, current.organizer_chain_id.parse::<ChainId>())
+        {
+            let host_chain_id = self.runtime.chain_id();
+            self.runtime.send_message(
+                organizer,
+                CrossChainMessage::TournamentResult {
+                    tournament_id: current.tournament_id,
+                    round: current.round,
+                    host_chain_id,
+                    winner_chain_id,
+                },
+            );
+        }
+    }
+
+    fn escrow_owner(&mut self) -> AccountOwner {
+        AccountOwner::from(self.runtime.application_id().forget_abi())
+    }
+
+    /// Moves a stake from this chain's balance into the application's escrow account on the host chain.
+    fn lock_stake(&mut self, host_chain_id: ChainId, amount: Amount) {
+        if amount == Amount::ZERO {
+            return;
+        }
+        let escrow = Account {
+            chain_id: host_chain_id,
+            owner: self.escrow_owner(),
+        };
+        self.runtime.transfer(AccountOwner::CHAIN, escrow, amount);
+    }
+
+    /// Pays from the escrow account on this (host) chain to a player chain's balance.
+    fn pay_stake(&mut self, chain_id: &str, amount: Amount) {
+        let Ok(chain_id) = chain_id.parse::<ChainId>() else {
+            return;
+        };
+        if amount == Amount::ZERO {
+            return;
+        }
+        let source = self.escrow_owner();
+        let destination = Account {
+            chain_id,
+            owner: AccountOwner::CHAIN,
+        };
+        self.runtime.transfer(source, destination, amount);
+    }
+
+    /// Pays out the stakes escrowed by the room hosted here once it has ended.
+    fn settle_stakes(&mut self, room: &mut Room) {
+        if room.stakes.is_empty() || room.status == RoomStatus::Active || !self.is_host(room) {
+            return;
+        }
+        // A pot too large to add up is refunded rather than paid out short.
+        let payouts = stake_payouts(room).unwrap_or_else(|_| {
+            room.stakes
+                .iter()
+                .map(|s| (s.chain_id.clone(), s.amount))
+                .collect()
+        });
+        for (chain_id, amount) in payouts {
+            self.pay_stake(&chain_id, amount);
+        }
+        room.stakes.clear();
+    }
+
+    /// Votes for a draw or abort on every player's copy of the room.
+    fn vote_to_end(&mut self, proposal: Proposal, accepting: bool) {
+        let mut room = self.ensure_room_mut();
+        let player_chain_id = self.runtime.chain_id();
+        let agreed =
+            match agree_to_end(&mut room, proposal, &player_chain_id.to_string(), accepting) {
+                Ok(agreed) => agreed,
+                Err(err) => panic!("{}", err),
+            };
+        let message = match (proposal, accepting) {
+            (Proposal::Draw, false) => CrossChainMessage::DrawOffered { player_chain_id },
+            (Proposal::Draw, true) => CrossChainMessage::DrawAccepted { player_chain_id },
+            (Proposal::Abort, false) => CrossChainMessage::AbortProposed { player_chain_id },
+            (Proposal::Abort, true) => CrossChainMessage::AbortAccepted { player_chain_id },
+        };
+        for player in self.other_players(&room) {
+            self.runtime.send_message(player, message.clone());
+        }
+        let notification = match (proposal, agreed) {
+            (Proposal::Draw, true) => "Game drawn",
+            (Proposal::Abort, true) => "Room aborted",
+            (Proposal::Draw, false) => "Draw offered",
+            (Proposal::Abort, false) => "Abort proposed",
+        };
+        self.set_room(room);
+        if agreed {
+            self.refresh_listing();
+        }
+        self.state
+            .last_notification
+            .set(Some(notification.to_string()));
+    }
+
+    fn receive_vote_to_end(
+        &mut self,
+        player_chain_id: ChainId,
+        proposal: Proposal,
+        accepting: bool,
+    ) {
+        let mut room = self.ensure_room_mut();
+        self.authenticate_player(&room, player_chain_id);
+        let voter = player_chain_id.to_string();
+        let name = room
+            .players
+            .iter()
+            .find(|p| p.chain_id == voter)
+            .map(|p| p.name.clone())
+            .unwrap_or_else(|| voter.clone());
+        // A vote that crossed with the end of the game is stale; drop it rather than bounce.
+        let Ok(agreed) = agree_to_end(&mut room, proposal, &voter, accepting) else {
+            return;
+        };
+        let notification = match (proposal, agreed) {
+            (Proposal::Draw, true) => "Game drawn".to_string(),
+            (Proposal::Abort, true) => "Room aborted".to_string(),
+            (Proposal::Draw, false) => format!("{} offers a draw", name),
+            (Proposal::Abort, false) => format!("{} proposes to abort the room", name),
+        };
+        self.set_room(room);
+        if agreed {
+            self.refresh_listing();
+        }
+        self.state.last_notification.set(Some(notification));
+    }
+
+    /// Opens a duel hosted here against `guest`, as paired by an orchestrator or a tournament organizer.
+    /// Panics if this chain is busy in another room.
+    fn host_match(
+        &mut self,
+        host_name: String,
+        guest_chain_id: ChainId,
+        guest_name: String,
+        settings: RoomSettings,
+    ) -> Room {
+        if let Some(room) = self.state.room.get().clone() {
+            if room.status == RoomStatus::Active {
+                panic!("Already in an active room");
+            }
+        }
+        if let Err(err) = validate_room_settings(BOARD_SIZE, &settings) {
+            panic!("{}", err);
+        }
+
+        self.state.board.set(None);
+        self.state.enemy_views.set(Vec::new());
+        let mut room = self.open_room(host_name, settings, None);
+        room.players.push(PlayerInfo {
+            chain_id: guest_chain_id.to_string(),
+            name: guest_name,
+            board_submitted: false,
+            ships_afloat: 0,
+            weapons_used: Vec::new(),
+            eliminated: false,
+            team: None,
+            series_wins: 0,
+        });
+        assign_teams(&mut room);
+        room.game_state = GameState::PlacingBoards;
+        self.set_room(room.clone());
+        self.ensure_enemy_views_created();
+        self.send_tracked(
+            guest_chain_id,
+            CrossChainMessage::InitialStateSync { room: room.clone() },
+        );
+        room
+    }
+
+    /// Tells the hosts of newly paired tournament matches to open their rooms.
+    fn send_tournament_matches(&mut self, tournament: &Tournament, matches: &[TournamentMatch]) {
+        let name_of = |chain_id: &str| {
+            tournament
+                .players
+                .iter()
+                .find(|p| p.chain_id == chain_id)
+                .map(|p| p.name.clone())
+                .unwrap_or_default()
+        };
+        for game in matches {
+            let Some(guest) = &game.guest_chain_id else {
+                continue;
+            };
+            let (Ok(host_chain_id), Ok(guest_chain_id)) = (
+                game.host_chain_id.parse::<ChainId>(),
+                guest.parse::<ChainId>(),
+            ) else {
+                continue;
+            };
+            self.runtime.send_message(
+                host_chain_id,
+                CrossChainMessage::TournamentMatchStart {
+                    tournament_id: tournament.tournament_id.clone(),
+                    round: game.round,
+                    host_name: name_of(&game.host_chain_id),
+                    guest_chain_id,
+                    guest_name: name_of(guest),
+                    settings: tournament.settings.clone(),
+                },
+            );
+            self.runtime.send_message(
+                guest_chain_id,
+                CrossChainMessage::TournamentMatchFound { host_chain_id },
+            );
+        }
+    }
+
+    /// Records a match result; `reported` is the tournament and round named by a host's report.
+    fn decide_tournament_match(
+        &mut self,
+        reported: Option<(&str, u32)>,
+        host_chain_id: &str,
+        winner_chain_id: &str,
+    ) {
+        let mut tournament = self
+            .state
+            .tournament
+            .get()
+            .clone()
+            .expect("No tournament organized here");
+        if let Some((tournament_id, round)) = reported {
+            if tournament_id != tournament.tournament_id || round != tournament.round {
+                // The organizer already decided this match by hand.
+                return;
+            }
+        }
+        let matches =
+            match record_tournament_result(&mut tournament, host_chain_id, winner_chain_id) {
+                Ok(matches) => matches,
+                Err(err) => panic!("{}", err),
+            };
+        self.send_tournament_matches(&tournament, &matches);
+        self.state.tournament.set(Some(tournament));
     }
 
     fn is_host(&mut self, room: &Room) -> bool {
         room.host_chain_id == self.runtime.chain_id().to_string()
     }
 
-    fn find_enemy_chain_id(&mut self, room: &Room) -> Option<ChainId> {
+    fn other_players(&mut self, room: &Room) -> Vec<ChainId> {
         let self_chain = self.runtime.chain_id().to_string();
         room.players
             .iter()
-            .find(|p| p.chain_id != self_chain)
-            .and_then(|p| p.chain_id.parse().ok())
+            .filter(|p| p.chain_id != self_chain)
+            .filter_map(|p| p.chain_id.parse().ok())
+            .collect()
     }
 
-    fn ensure_enemy_view_created(&mut self, enemy_chain_id: &str) {
-        let already = self.state
-            .enemy_view
+    fn sync_room_to_guests(&mut self, room: &Room, except: Option<ChainId>) {
+        for guest in self.other_players(room) {
+            if Some(guest) != except {
+                self.runtime
+                    .send_message(guest, CrossChainMessage::RoomSync { room: room.clone() });
+            }
+        }
+    }
+
+    fn send_turn_sync(&mut self, room: &Room, attacker_chain_id: ChainId, shots: &[ShotResult]) {
+        let defender_chain_id = self.runtime.chain_id();
+        for player in self.other_players(room) {
+            if player != attacker_chain_id {
+                self.runtime.send_message(
+                    player,
+                    CrossChainMessage::TurnSync {
+                        defender_chain_id,
+                        attacker_chain_id,
+                        shots: shots.to_vec(),
+                        room: room.clone(),
+                    },
+                );
+            }
+        }
+    }
+
+    fn share_board_with_teammate(&mut self, room: &Room) {
+        let player_chain_id = self.runtime.chain_id();
+        let Some(teammate) = teammate_of(room, &player_chain_id.to_string()) else {
+            return;
+        };
+        let Some(board) = self.state.board.get().clone() else {
+            return;
+        };
+        if let Ok(teammate) = teammate.parse::<ChainId>() {
+            self.runtime.send_message(
+                teammate,
+                CrossChainMessage::TeammateBoard {
+                    player_chain_id,
+                    board,
+                },
+            );
+        }
+    }
+
+    fn ensure_enemy_views_created(&mut self) {
+        let Some(room) = self.state.room.get().clone() else {
+            return;
+        };
+        let self_chain = self.runtime.chain_id().to_string();
+        let mut views = self.state.enemy_views.get().clone();
+        for player in &room.players {
+            if player.chain_id != self_chain
+                && !views.iter().any(|v| v.owner_chain_id == player.chain_id)
+            {
+                views.push(empty_enemy_view(
+                    &player.chain_id,
+                    BOARD_SIZE,
+                    &room.settings.islands,
+                ));
+            }
+        }
+        self.state.enemy_views.set(views);
+    }
+
+    fn enemy_view_for(&self, room: &Room, owner_chain_id: &str) -> EnemyBoardView {
+        self.state
+            .enemy_views
             .get()
-            .as_ref()
-            .map(|_| true)
-            .unwrap_or(false);
-        if already {
+            .iter()
+            .find(|v| v.owner_chain_id == owner_chain_id)
+            .cloned()
+            .unwrap_or_else(|| empty_enemy_view(owner_chain_id, BOARD_SIZE, &room.settings.islands))
+    }
+
+    fn store_enemy_view(&mut self, view: EnemyBoardView) {
+        let mut views = self.state.enemy_views.get().clone();
+        match views
+            .iter_mut()
+            .find(|v| v.owner_chain_id == view.owner_chain_id)
+        {
+            Some(existing) => *existing = view,
+            None => views.push(view),
+        }
+        self.state.enemy_views.set(views);
+    }
+
+    fn choose_target(&mut self, room: &Room, target_chain_id: Option<String>) -> ChainId {
+        let self_chain = self.runtime.chain_id().to_string();
+        match resolve_target(room, &self_chain, target_chain_id.as_deref()) {
+            Ok(target) => target.parse().expect("Invalid target chain ID"),
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    fn send_tracked(&mut self, destination: ChainId, message: CrossChainMessage) {
+        self.runtime
+            .prepare_message(message)
+            .with_tracking()
+            .send_to(destination);
+    }
+
+    fn handle_bounced_message(&mut self, message: CrossChainMessage) {
+        if let CrossChainMessage::JoinRequest { .. } = message {
+            let host = self.state.pending_join.get().clone();
+            self.state.pending_join.set(None);
+            self.state.last_notification.set(Some(format!(
+                "Join request rejected by {}",
+                host.unwrap_or_else(|| "host".to_string())
+            )));
+            return;
+        }
+        let Some(rejected_by) = self.runtime.message_origin_chain_id() else {
+            return;
+        };
+        if let CrossChainMessage::MatchmakingStart { guest_chain_id, .. } = message {
+            self.runtime.send_message(
+                guest_chain_id,
+                CrossChainMessage::MatchmakingCancelled {
+                    host_chain_id: rejected_by,
+                },
+            );
+            return;
+        }
+        let self_chain = self.runtime.chain_id().to_string();
+        let mut room = self.state.room.get().clone();
+        let rollback = battleship_game::roll_back_bounced_message(
+            room.as_mut(),
+            &message,
+            &self_chain,
+            &rejected_by.to_string(),
+        );
+        if let Some((chain_id, stake)) = &rollback.refund {
+            self.pay_stake(chain_id, *stake);
+        }
+        if let (true, Some(room)) = (rollback.room_changed, room) {
+            self.set_room(room.clone());
+            if let CrossChainMessage::InitialStateSync { .. } = message {
+                self.sync_room_to_guests(&room, None);
+                self.refresh_listing();
+            }
+        }
+        if let Some(notification) = rollback.notification {
+            self.state.last_notification.set(Some(notification));
+        }
+    }
+
+    fn detonate_mine(&mut self, room: &mut Room) {
+        let Some(mut board) = self.state.board.get().clone() else {
+            return;
+        };
+        let seed = self.random_seed();
+        let Some(target) = mine_blast_target(&board, seed) else {
+            return;
+        };
+        let Ok(outcome) = apply_attack(&mut board, target.row, target.col) else {
+            return;
+        };
+        let mut shot = ShotResult {
+            row: target.row,
+            col: target.col,
+            hit: outcome.hit,
+            sunk: outcome.sunk,
+            mine: outcome.mine,
+            sunk_ship_cells: None,
+            adjacent_coords: None,
+        };
+        if outcome.sunk {
+            if let Some(ship_id) = outcome.ship_id {
+                let placement_rule = room.settings.placement_rule;
+                if let Ok((ship_cells, adjacent)) =
+                    apply_sunk_padding(&mut board, ship_id, placement_rule)
+                {
+                    shot.sunk_ship_cells = Some(ship_cells);
+                    shot.adjacent_coords = Some(adjacent);
+                }
+            }
+        }
+        self.state.board.set(Some(board));
+        self.share_board_with_teammate(room);
+
+        let player_chain_id = self.runtime.chain_id();
+        if outcome.sunk {
+            record_ship_sunk(room, &player_chain_id.to_string());
+        }
+        if outcome.game_over {
+            eliminate_player(room, &player_chain_id.to_string());
+            finish_if_last_standing(room);
+        }
+        self.state.last_notification.set(Some(format!(
+            "Mine blast revealed your cell ({},{})",
+            target.row, target.col
+        )));
+        // Every opponent learns the revealed cell, not only the mine owner.
+        for player in self.other_players(room) {
+            self.send_tracked(
+                player,
+                CrossChainMessage::MineBlast {
+                    player_chain_id,
+                    shot: shot.clone(),
+                    fleet_destroyed: outcome.game_over,
+                },
+            );
+        }
+    }
+
+    /// Keeps the lobby entry of the room hosted here up to date, and withdraws it once the room
+    /// is full, started, ended or gone.
+    fn refresh_listing(&mut self) {
+        let Some(published) = self.state.published_room.get().clone() else {
             return;
+        };
+        let Ok(orchestrator) = published.orchestrator_chain_id.parse::<ChainId>() else {
+            return;
+        };
+        let host_chain_id = self.runtime.chain_id();
+        let room = self.state.room.get().clone();
+        match room {
+            Some(room)
+                if room.room_id == published.room_id
+                    && room.host_chain_id == host_chain_id.to_string()
+                    && is_open_for_listing(&room) =>
+            {
+                let listed_at = self.runtime.system_time().micros().to_string();
+                let listing = room_listing(&room, &published.room_name, listed_at);
+                self.runtime
+                    .send_message(orchestrator, CrossChainMessage::ListRoom { listing });
+            }
+            _ => {
+                self.runtime.send_message(
+                    orchestrator,
+                    CrossChainMessage::UnlistRoom { host_chain_id },
+                );
+                self.state.published_room.set(None);
+            }
+        }
+    }
+
+    /// Tells every waiting player (from `from` on) where they stand in this orchestrator's queue.
+    fn send_queue_status(&mut self, queue: &[MatchmakingPlayer], from: usize) {
+        let orchestrator_chain_id = self.runtime.chain_id();
+        let estimated_wait_secs = average_wait_secs(self.state.recent_match_waits.get());
+        for (index, player) in queue.iter().enumerate().skip(from) {
+            if let Ok(player_chain) = player.chain_id.parse::<ChainId>() {
+                self.runtime.send_message(
+                    player_chain,
+                    CrossChainMessage::MatchmakingEnqueued {
+                        orchestrator_chain_id,
+                        position: index as u32 + 1,
+                        queue_size: queue.len() as u32,
+                        estimated_wait_secs,
+                    },
+                );
+            }
+        }
+    }
+
+    fn forward_stale_waiters(&mut self) {
+        let parameters = self.runtime.application_parameters();
+        let Some(sibling) = parameters.sibling_shard(self.runtime.chain_id()) else {
+            return;
+        };
+        let now = self.runtime.system_time().micros();
+        let mut queue = self.state.matchmaking_queue.get().clone();
+        let (first, stale) = take_stale_waiters(
+            &mut queue,
+            now,
+            parameters.stale_wait_secs,
+            parameters.matchmaking_shards.len(),
+        );
+        if stale.is_empty() {
+            return;
+        }
+        self.state.matchmaking_queue.set(queue.clone());
+        self.send_queue_status(&queue, first);
+        for player in stale {
+            let Ok(player_chain_id) = player.chain_id.parse::<ChainId>() else {
+                continue;
+            };
+            self.runtime.send_message(
+                sibling,
+                CrossChainMessage::MatchmakingEnqueue {
+                    player_chain_id,
+                    player_name: player.player_name,
+                    preferences: player.preferences,
+                    hops: player.hops + 1,
+                },
+            );
+        }
+    }
+
+    fn random_seed(&mut self) -> u64 {
+        let chain_id = self.runtime.chain_id().to_string();
+        chain_seed(&chain_id, self.runtime.block_height().0) ^ self.runtime.system_time().micros()
+    }
+
+    fn open_room(
+        &mut self,
+        host_name: String,
+        settings: RoomSettings,
+        join_code: Option<String>,
+    ) -> Room {
+        let chain_id = self.runtime.chain_id().to_string();
+        let room_id = self.runtime.system_time().micros().to_string();
+        let code_hash = join_code.map(|code| hash_join_code(&room_id, &code));
+        self.state.join_code_hash.set(code_hash);
+        let mut room = Room {
+            room_id,
+            host_chain_id: chain_id.clone(),
+            status: RoomStatus::Active,
+            game_state: GameState::WaitingForPlayer,
+            settings,
+            private: code_hash.is_some(),
+            players: vec![PlayerInfo {
+                chain_id,
+                name: host_name,
+                board_submitted: false,
+                ships_afloat: 0,
+                weapons_used: Vec::new(),
+                eliminated: false,
+                team: None,
+                series_wins: 0,
+            }],
+            current_attacker: None,
+            pending_attack: None,
+            pending_salvo: None,
+            pending_weapon: None,
+            pending_target: None,
+            skip_next_turn: Vec::new(),
+            winner_chain_id: None,
+            game_number: 1,
+            stakes: Vec::new(),
+            draw_votes: Vec::new(),
+            abort_votes: Vec::new(),
+            last_activity_at: 0,
+        };
+        assign_teams(&mut room);
+        self.set_room(room.clone());
+        self.state.last_reveal.set(None);
+        self.refresh_listing();
+        room
+    }
+
+    fn submit_board(
+        &mut self,
+        ships: &[ShipPlacementInput],
+        shapes: &[ShapePlacementInput],
+        mines: &[Coord],
+    ) {
+        let mut room = self.ensure_room_mut();
+        let board = validate_and_build_board(BOARD_SIZE, ships, shapes, mines, &room.settings)
+            .expect("Invalid board");
+        let ship_count = board.ships.len() as u8;
+        self.state.board.set(Some(board));
+
+        let self_chain = self.runtime.chain_id().to_string();
+        if let Some(p) = room.players.iter_mut().find(|p| p.chain_id == self_chain) {
+            p.board_submitted = true;
+            p.ships_afloat = ship_count;
+        }
+        self.set_room(room.clone());
+
+        if self.is_host(&room) {
+            self.ensure_enemy_views_created();
+            self.sync_room_to_guests(&room, None);
+        } else if let Ok(host_chain) = room.host_chain_id.parse::<ChainId>() {
+            let player_chain_id = self.runtime.chain_id();
+            self.send_tracked(
+                host_chain,
+                CrossChainMessage::BoardSubmittedNotice {
+                    player_chain_id,
+                    ship_count,
+                },
+            );
+        }
+    }
+
+    fn start_game(&mut self) {
+        let mut room = self.ensure_room_mut();
+        if !self.is_host(&room) {
+            panic!("Only host can start game");
+        }
+        if room.status != RoomStatus::Active {
+            panic!("Room not active");
+        }
+        if room.players.len() < 2 {
+            panic!("Need at least 2 players");
+        }
+        if room.settings.teams && room.players.len() != 4 {
+            panic!("Team games need 4 players");
+        }
+        if !room.players.iter().all(|p| p.board_submitted) {
+            panic!("All boards must be submitted");
+        }
+        let host_chain_id = self.runtime.chain_id();
+        let sent_invites = self.state.sent_invitations.get().clone();
+        for target in sent_invites {
+            if let Ok(target_chain) = target.parse::<ChainId>() {
+                self.runtime.send_message(
+                    target_chain,
+                    CrossChainMessage::RoomInvitationCancelled { host_chain_id },
+                );
+            }
         }
+        self.state.sent_invitations.set(Vec::new());
+        room.game_state = GameState::InGame;
+        room.current_attacker = series_first_attacker(&room);
+        room.pending_attack = None;
+        room.pending_salvo = None;
+        room.pending_weapon = None;
+        room.pending_target = None;
+        room.skip_next_turn = Vec::new();
+        self.set_room(room.clone());
+        self.sync_room_to_guests(&room, None);
+        self.share_board_with_teammate(&room);
+        self.refresh_listing();
+        self.bot_fire();
+    }
+
+    fn send_attack(&mut self, mut room: Room, enemy: ChainId, row: u8, col: u8) {
+        room.pending_attack = Some(battleship_game::Coord { row, col });
+        room.pending_target = Some(enemy.to_string());
+        self.set_room(room);
+        self.state.last_reveal.set(None);
+
+        let attacker_chain_id = self.runtime.chain_id();
+        let message = CrossChainMessage::AttackRequest {
+            attacker_chain_id,
+            row,
+            col,
+        };
+        self.send_tracked(enemy, message);
+    }
+
+    /// On a bot chain, makes sure an open room with a random board is waiting for the next challenger.
+    fn bot_host_room(&mut self) {
+        let Some(bot_name) = self.state.bot_name.get().clone() else {
+            return;
+        };
         if let Some(room) = self.state.room.get() {
-            if room.players.iter().any(|p| p.chain_id == enemy_chain_id) {
-                self.state.enemy_view.set(Some(empty_enemy_view(10)));
+            if room.status == RoomStatus::Active {
+                return;
+            }
+        }
+        let settings = RoomSettings::default();
+        let seed = self.random_seed();
+        let placements = random_board_placements(BOARD_SIZE, &STANDARD_FLEET, &settings, seed)
+            .expect("Bot could not place its fleet");
+        let board = validate_and_build_board(BOARD_SIZE, &placements, &[], &[], &settings)
+            .expect("Invalid bot board");
+        let ship_count = board.ships.len() as u8;
+        self.state.board.set(Some(board));
+        self.state.teammate_board.set(None);
+        self.state.enemy_views.set(Vec::new());
+        let mut room = self.open_room(bot_name, settings, None);
+        room.players[0].board_submitted = true;
+        room.players[0].ships_afloat = ship_count;
+        self.set_room(room);
+    }
+
+    fn send_salvo(&mut self, mut room: Room, enemy: ChainId, shots: Vec<Coord>) {
+        room.pending_salvo = Some(shots.clone());
+        room.pending_target = Some(enemy.to_string());
+        self.set_room(room);
+        self.state.last_reveal.set(None);
+
+        let attacker_chain_id = self.runtime.chain_id();
+        self.send_tracked(
+            enemy,
+            CrossChainMessage::SalvoAttackRequest {
+                attacker_chain_id,
+                shots,
+            },
+        );
+    }
+
+    /// On a bot chain, fires the next shot or salvo if it is the bot's turn, or places a new fleet
+    /// when the next game of a series begins.
+    fn bot_fire(&mut self) {
+        if self.state.bot_name.get().is_none() {
+            return;
+        }
+        let Some(room) = self.state.room.get().clone() else {
+            return;
+        };
+        let self_chain = self.runtime.chain_id().to_string();
+        let placing = room
+            .players
+            .iter()
+            .any(|p| p.chain_id == self_chain && !p.board_submitted);
+        if room.game_state == GameState::PlacingBoards
+            && room.status == RoomStatus::Active
+            && placing
+        {
+            let fleet = if room.settings.fleet.is_empty() {
+                STANDARD_FLEET.to_vec()
+            } else {
+                room.settings.fleet.clone()
+            };
+            let seed = self.random_seed();
+            let placements = random_board_placements(BOARD_SIZE, &fleet, &room.settings, seed)
+                .expect("Bot could not place its fleet");
+            self.submit_board(&placements, &[], &[]);
+            let room = self.ensure_room_mut();
+            if self.is_host(&room)
+                && room.players.len() >= 2
+                && room.players.iter().all(|p| p.board_submitted)
+            {
+                self.start_game();
+            }
+            return;
+        }
+        if !bot_can_fire(&room, &self_chain) {
+            return;
+        }
+        let Ok(target) = resolve_target(&room, &self_chain, None) else {
+            return;
+        };
+        let view = self.enemy_view_for(&room, &target);
+        let seed = self.random_seed();
+        let enemy: ChainId = target.parse().expect("Invalid target chain ID");
+        if room.settings.game_mode == GameMode::Salvo {
+            // The bot always fires every shot it is allowed.
+            let shots = choose_bot_salvo(&view, salvo_shot_count(&room, &self_chain), seed);
+            if !shots.is_empty() {
+                self.send_salvo(room, enemy, shots);
             }
+            return;
+        }
+        let Some(shot) = choose_bot_shot(&view, seed) else {
+            return;
+        };
+        self.send_attack(room, enemy, shot.row, shot.col);
+    }
+
+    fn authenticate_origin(&mut self, claimed: ChainId) {
+        let origin = self.runtime.message_origin_chain_id();
+        if let Err(err) = authenticate_sender(origin, claimed) {
+            panic!("{}", err);
+        }
+    }
+
+    fn authenticate_player(&mut self, room: &Room, claimed: ChainId) {
+        let origin = self.runtime.message_origin_chain_id();
+        if let Err(err) = authenticate_room_player(room, origin, claimed) {
+            panic!("{}", err);
+        }
+    }
+
+    fn authenticate_orchestrator(&mut self) {
+        let parameters = self.runtime.application_parameters();
+        let origin = self.runtime.message_origin_chain_id();
+        if let Err(err) = authenticate_orchestrator(&parameters, origin) {
+            panic!("{}", err);
+        }
+    }
+
+    /// Checks that the message comes from the organizer this chain registered with, and returns it.
+    fn authenticate_organizer(&mut self) -> String {
+        let Some(organizer_chain_id) = self.state.tournament_organizer.get().clone() else {
+            panic!("Not registered for a tournament");
+        };
+        let organizer: ChainId = organizer_chain_id
+            .parse()
+            .expect("Invalid organizer chain ID");
+        self.authenticate_origin(organizer);
+        organizer_chain_id
+    }
+
+    fn ensure_orchestrator(&mut self) {
+        let chain_id = self.runtime.chain_id();
+        if !self
+            .runtime
+            .application_parameters()
+            .is_orchestrator(chain_id)
+        {
+            panic!("This chain is not an official orchestrator");
+        }
+    }
+
+    fn choose_orchestrator(&mut self, requested: Option<String>) -> ChainId {
+        match self
+            .runtime
+            .application_parameters()
+            .choose_orchestrator(requested.as_deref())
+        {
+            Ok(chain_id) => chain_id,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    fn authenticate_host(&mut self, room: &Room) {
+        let origin = self.runtime.message_origin_chain_id();
+        if let Err(err) = authenticate_room_host(room, origin) {
+            panic!("{}", err);
         }
     }
 }
 
 impl Contract for BattleshipContract {
     type Message = CrossChainMessage;
-    type InstantiationArgument = ();
-    type Parameters = ();
+    type InstantiationArgument = Option<BotConfig>;
+    type Parameters = BattleshipParameters;
     type EventValue = ();
 
     async fn load(runtime: ContractRuntime<Self>) -> Self {
@@ -77,11 +963,14 @@ impl Contract for BattleshipContract {
         BattleshipContract { state, runtime }
     }
 
-    async fn instantiate(&mut self, _argument: ()) {
+    async fn instantiate(&mut self, argument: Option<BotConfig>) {
+        self.state.bot_name.set(argument.map(|config| config.name));
         self.state.room.set(None);
         self.state.board.set(None);
-        self.state.enemy_view.set(None);
+        self.state.teammate_board.set(None);
+        self.state.enemy_views.set(Vec::new());
         self.state.subscribed_to_host.set(None);
+        self.state.pending_join.set(None);
         self.state.last_reveal.set(None);
         self.state.last_notification.set(None);
         self.state.matchmaking_queue.set(Vec::new());
@@ -89,45 +978,75 @@ impl Contract for BattleshipContract {
 
     async fn execute_operation(&mut self, operation: Operation) -> () {
         match operation {
-            Operation::CreateRoom { host_name } => {
-                let chain_id = self.runtime.chain_id().to_string();
-                let room_id = self.runtime.system_time().micros().to_string();
-                let room = Room {
-                    room_id: room_id.clone(),
-                    host_chain_id: chain_id.clone(),
-                    status: RoomStatus::Active,
-                    game_state: GameState::WaitingForPlayer,
-                    players: vec![PlayerInfo {
-                        chain_id: chain_id.clone(),
-                        name: host_name,
-                        board_submitted: false,
-                    }],
-                    current_attacker: None,
-                    pending_attack: None,
-                    winner_chain_id: None,
-                };
-                self.set_room(room.clone());
-                self.state.last_reveal.set(None);
+            Operation::CreateRoom {
+                host_name,
+                settings,
+                join_code,
+            } => {
+                if let Err(err) = validate_room_settings(BOARD_SIZE, &settings) {
+                    panic!("{}", err);
+                }
+                if join_code
+                    .as_deref()
+                    .is_some_and(|code| code.trim().is_empty())
+                {
+                    panic!("Join code must not be empty");
+                }
+                let stake = settings.stake;
+                let mut room = self.open_room(host_name, settings, join_code);
+                if stake > Amount::ZERO {
+                    let host_chain_id = self.runtime.chain_id();
+                    self.lock_stake(host_chain_id, stake);
+                    room.stakes.push(Stake {
+                        chain_id: host_chain_id.to_string(),
+                        amount: stake,
+                    });
+                    self.set_room(room);
+                }
             }
 
             Operation::JoinRoom {
                 host_chain_id,
                 player_name,
+                join_code,
+                stake,
             } => {
                 let target_chain: ChainId = host_chain_id.parse().expect("Invalid host chain ID");
+                self.lock_stake(target_chain, stake);
                 let message = CrossChainMessage::JoinRequest {
                     player_chain_id: self.runtime.chain_id(),
                     player_name,
+                    join_code,
+                    stake,
                 };
-                self.runtime.send_message(target_chain, message);
+                self.state.pending_join.set(Some(host_chain_id));
+                self.send_tracked(target_chain, message);
             }
 
             Operation::SearchPlayer {
                 orchestrator_chain_id,
                 player_name,
+                preferences,
+                region,
             } => {
-                let orchestrator: ChainId =
-                    orchestrator_chain_id.parse().expect("Invalid orchestrator chain ID");
+                if let Err(err) = validate_preferences(&preferences) {
+                    panic!("{}", err);
+                }
+                // A player already waiting searches again on the shard that holds them.
+                let queued_on = self
+                    .state
+                    .matchmaking_status
+                    .get()
+                    .as_ref()
+                    .map(|s| s.orchestrator_chain_id.clone());
+                let shard = self
+                    .runtime
+                    .application_parameters()
+                    .shard_for(region.as_deref(), preferences.game_mode);
+                let orchestrator = match (orchestrator_chain_id, queued_on, shard) {
+                    (None, None, Some(shard)) => shard,
+                    (requested, queued_on, _) => self.choose_orchestrator(requested.or(queued_on)),
+                };
                 let player_chain_id = self.runtime.chain_id();
                 self.state
                     .last_notification
@@ -137,126 +1056,333 @@ impl Contract for BattleshipContract {
                     CrossChainMessage::MatchmakingEnqueue {
                         player_chain_id,
                         player_name,
+                        preferences,
+                        hops: 0,
                     },
                 );
             }
 
-            Operation::SubmitBoard { ships } => {
-                let board = validate_and_build_board(10, &ships).expect("Invalid board");
-                self.state.board.set(Some(board));
+            Operation::SubmitBoard {
+                ships,
+                shapes,
+                mines,
+            } => {
+                self.submit_board(&ships, &shapes, &mines);
+            }
 
-                let mut room = self.ensure_room_mut();
-                let self_chain = self.runtime.chain_id().to_string();
-                if let Some(p) = room.players.iter_mut().find(|p| p.chain_id == self_chain) {
-                    p.board_submitted = true;
+            Operation::SaveLayout { name, ships } => {
+                let mut layouts = self.state.saved_layouts.get().clone();
+                if let Err(err) = save_layout(&mut layouts, SavedLayout { name, ships }) {
+                    panic!("{}", err);
                 }
-                self.set_room(room.clone());
+                self.state.saved_layouts.set(layouts);
+            }
 
-                if self.is_host(&room) {
-                    if let Some(enemy) = self.find_enemy_chain_id(&room) {
-                        self.ensure_enemy_view_created(&enemy.to_string());
-                        self.runtime.send_message(enemy, CrossChainMessage::RoomSync { room });
-                    }
-                } else if let Ok(host_chain) = room.host_chain_id.parse::<ChainId>() {
-                    let player_chain_id = self.runtime.chain_id();
-                    self.runtime.send_message(
-                        host_chain,
-                        CrossChainMessage::BoardSubmittedNotice {
-                            player_chain_id,
-                        },
-                    );
-                }
+            Operation::DeleteLayout { name } => {
+                let mut layouts = self.state.saved_layouts.get().clone();
+                layouts.retain(|l| l.name != name);
+                self.state.saved_layouts.set(layouts);
+            }
+
+            Operation::SubmitSavedLayout { name } => {
+                let layout = self
+                    .state
+                    .saved_layouts
+                    .get()
+                    .iter()
+                    .find(|l| l.name == name)
+                    .cloned()
+                    .expect("Layout not found");
+                self.submit_board(&layout.ships, &[], &[]);
             }
 
             Operation::StartGame => {
-                let mut room = self.ensure_room_mut();
-                if !self.is_host(&room) {
-                    panic!("Only host can start game");
-                }
-                if room.status != RoomStatus::Active {
-                    panic!("Room not active");
+                self.start_game();
+            }
+
+            Operation::Attack {
+                row,
+                col,
+                target_chain_id,
+            } => {
+                let room = self.ensure_room_mut();
+                if room.game_state != GameState::InGame {
+                    panic!("Game not started");
                 }
-                if room.players.len() != 2 {
-                    panic!("Need 2 players");
+                if room.settings.game_mode != GameMode::Classic {
+                    panic!("Room uses salvo attacks");
                 }
-                if !room.players.iter().all(|p| p.board_submitted) {
-                    panic!("Both boards must be submitted");
+                let self_chain = self.runtime.chain_id().to_string();
+                if room.current_attacker.as_deref() != Some(self_chain.as_str()) {
+                    panic!("Not your turn");
                 }
-                let host_chain_id = self.runtime.chain_id();
-                let sent_invites = self.state.sent_invitations.get().clone();
-                for target in sent_invites {
-                    if let Ok(target_chain) = target.parse::<ChainId>() {
-                        self.runtime.send_message(
-                            target_chain,
-                            CrossChainMessage::RoomInvitationCancelled { host_chain_id },
-                        );
-                    }
+                if room.pending_attack.is_some() || room.pending_weapon.is_some() {
+                    panic!("Pending attack not resolved");
                 }
-                self.state.sent_invitations.set(Vec::new());
-                let host_chain = room.host_chain_id.clone();
-                room.game_state = GameState::InGame;
-                room.current_attacker = Some(host_chain.clone());
-                room.pending_attack = None;
-                self.set_room(room.clone());
-                if let Some(enemy) = self.find_enemy_chain_id(&room) {
-                    self.runtime.send_message(enemy, CrossChainMessage::RoomSync { room });
+
+                let enemy = self.choose_target(&room, target_chain_id);
+                let view = self.enemy_view_for(&room, &enemy.to_string());
+                let idx = (row as usize) * (view.size as usize) + (col as usize);
+                if idx < view.cells.len() && view.cells[idx] != EnemyCell::Unknown {
+                    panic!("Cell already revealed");
                 }
+                self.send_attack(room, enemy, row, col);
             }
 
-            Operation::Attack { row, col } => {
-                let mut room = self.ensure_room_mut();
+            Operation::Salvo {
+                shots,
+                target_chain_id,
+            } => {
+                let room = self.ensure_room_mut();
                 if room.game_state != GameState::InGame {
                     panic!("Game not started");
                 }
+                if room.settings.game_mode != GameMode::Salvo {
+                    panic!("Room does not use salvo attacks");
+                }
                 let self_chain = self.runtime.chain_id().to_string();
-                if room.current_attacker.as_deref() != Some(&self_chain) {
+                if room.current_attacker.as_deref() != Some(self_chain.as_str()) {
                     panic!("Not your turn");
                 }
-                if room.pending_attack.is_some() {
-                    panic!("Pending attack not resolved");
+                if room.pending_salvo.is_some() || room.pending_weapon.is_some() {
+                    panic!("Pending salvo not resolved");
+                }
+                let allowed = salvo_shot_count(&room, &self_chain);
+                if shots.is_empty() || shots.len() > allowed as usize {
+                    panic!("Salvo must contain between 1 and {} shots", allowed);
+                }
+                for (i, shot) in shots.iter().enumerate() {
+                    if shots[..i].contains(shot) {
+                        panic!("Duplicate shot in salvo");
+                    }
                 }
 
-                if let Some(view) = self.state.enemy_view.get().clone() {
-                    let idx = (row as usize) * (view.size as usize) + (col as usize);
+                let enemy = self.choose_target(&room, target_chain_id);
+                let view = self.enemy_view_for(&room, &enemy.to_string());
+                for shot in &shots {
+                    let idx = (shot.row as usize) * (view.size as usize) + (shot.col as usize);
                     if idx < view.cells.len() && view.cells[idx] != EnemyCell::Unknown {
                         panic!("Cell already revealed");
                     }
                 }
 
-                let enemy = self.find_enemy_chain_id(&room).expect("Enemy not found");
-                room.pending_attack = Some(battleship_game::Coord { row, col });
+                self.send_salvo(room, enemy, shots);
+            }
+
+            Operation::UseWeapon {
+                weapon,
+                row,
+                col,
+                target_chain_id,
+            } => {
+                let mut room = self.ensure_room_mut();
+                if room.game_state != GameState::InGame {
+                    panic!("Game not started");
+                }
+                let self_chain = self.runtime.chain_id().to_string();
+                if room.current_attacker.as_deref() != Some(self_chain.as_str()) {
+                    panic!("Not your turn");
+                }
+                if room.pending_attack.is_some()
+                    || room.pending_salvo.is_some()
+                    || room.pending_weapon.is_some()
+                {
+                    panic!("Pending attack not resolved");
+                }
+                if weapons_remaining(&room, &self_chain).uses(weapon) == 0 {
+                    panic!("No {:?} uses left", weapon);
+                }
+
+                let enemy = self.choose_target(&room, target_chain_id);
+                let shot = WeaponShot { weapon, row, col };
+                room.pending_weapon = Some(shot);
+                room.pending_target = Some(enemy.to_string());
                 self.set_room(room.clone());
                 self.state.last_reveal.set(None);
 
                 let attacker_chain_id = self.runtime.chain_id();
-                let message = CrossChainMessage::AttackRequest {
-                    attacker_chain_id,
-                    row,
-                    col,
-                };
-                self.runtime.send_message(enemy, message);
+                self.send_tracked(
+                    enemy,
+                    CrossChainMessage::WeaponAttackRequest {
+                        attacker_chain_id,
+                        shot,
+                    },
+                );
             }
 
             Operation::LeaveRoom => {
                 let room = self.state.room.get().clone();
-                if let Some(room) = room {
+                if let Some(mut room) = room {
                     if room.status == RoomStatus::Active {
-                        if let Some(enemy) = self.find_enemy_chain_id(&room) {
-                            let self_chain_id = self.runtime.chain_id();
-                            self.runtime.send_message(
-                                enemy,
+                        let self_chain_id = self.runtime.chain_id();
+                        for player in self.other_players(&room) {
+                            self.send_tracked(
+                                player,
                                 CrossChainMessage::LeaveNotice {
                                     player_chain_id: self_chain_id,
                                 },
                             );
                         }
+                        // Leaving forfeits to the remaining side when only one is left. If this chain
+                        // hosts a tournament match, that result goes to the organizer.
+                        room.winner_chain_id = forfeit_winner(&room, &self_chain_id.to_string());
+                        room.status = RoomStatus::Ended;
+                        self.report_tournament_result(&room);
+                        // The escrow leaves with the host: a started room is forfeited, otherwise stakes are refunded.
+                        if !room_started(&room) {
+                            room.winner_chain_id = None;
+                        }
+                        self.settle_stakes(&mut room);
                     }
                 }
                 self.state.room.set(None);
                 self.state.board.set(None);
-                self.state.enemy_view.set(None);
+                self.state.teammate_board.set(None);
+                self.state.enemy_views.set(Vec::new());
                 self.state.subscribed_to_host.set(None);
+                self.state.pending_join.set(None);
                 self.state.last_reveal.set(None);
+                self.refresh_listing();
+            }
+
+            Operation::PublishRoom {
+                orchestrator_chain_id,
+                room_name,
+            } => {
+                let orchestrator_chain_id =
+                    self.choose_orchestrator(orchestrator_chain_id).to_string();
+                let room = self.ensure_room_mut();
+                if !self.is_host(&room) {
+                    panic!("Only host can publish the room");
+                }
+                if !is_open_for_listing(&room) {
+                    panic!("Room is not open for players");
+                }
+                if let Some(previous) = self.state.published_room.get() {
+                    if previous.orchestrator_chain_id != orchestrator_chain_id {
+                        panic!(
+                            "Room is already listed on {}",
+                            previous.orchestrator_chain_id
+                        );
+                    }
+                }
+                self.state.published_room.set(Some(PublishedRoom {
+                    orchestrator_chain_id,
+                    room_id: room.room_id,
+                    room_name,
+                }));
+                self.refresh_listing();
+            }
+
+            Operation::ClaimTimeout => {
+                let mut room = self.ensure_room_mut();
+                let claimant_chain_id = self.runtime.chain_id();
+                let players = self.other_players(&room);
+                let now = self.runtime.system_time().micros();
+                let stalled = match claim_timeout(&mut room, &claimant_chain_id.to_string(), now) {
+                    Ok(stalled) => stalled,
+                    Err(err) => panic!("{}", err),
+                };
+                // Every copy applies the claim, so a stalled host settles the escrow once it runs again.
+                let stalled_chain_id: ChainId = stalled.parse().expect("Invalid player chain ID");
+                for player in players {
+                    self.send_tracked(
+                        player,
+                        CrossChainMessage::TimeoutClaim {
+                            claimant_chain_id,
+                            stalled_chain_id,
+                        },
+                    );
+                }
+                self.set_room(room);
+                self.state
+                    .last_notification
+                    .set(Some(format!("{} timed out", stalled)));
+                self.bot_fire();
+            }
+
+            Operation::OfferDraw => self.vote_to_end(Proposal::Draw, false),
+            Operation::AcceptDraw => self.vote_to_end(Proposal::Draw, true),
+            Operation::ProposeAbort => self.vote_to_end(Proposal::Abort, false),
+            Operation::AcceptAbort => self.vote_to_end(Proposal::Abort, true),
+
+            Operation::CreateTournament {
+                name,
+                format,
+                settings,
+                max_players,
+                rounds,
+            } => {
+                if let Some(tournament) = self.state.tournament.get() {
+                    if tournament.status != TournamentStatus::Finished {
+                        panic!("A tournament is already organized here");
+                    }
+                }
+                let tournament_id = self.runtime.system_time().micros().to_string();
+                match new_tournament(tournament_id, name, format, settings, max_players, rounds) {
+                    Ok(tournament) => self.state.tournament.set(Some(tournament)),
+                    Err(err) => panic!("{}", err),
+                }
+            }
+
+            Operation::StartTournament => {
+                let mut tournament = self
+                    .state
+                    .tournament
+                    .get()
+                    .clone()
+                    .expect("No tournament organized here");
+                let matches = match start_tournament(&mut tournament) {
+                    Ok(matches) => matches,
+                    Err(err) => panic!("{}", err),
+                };
+                self.send_tournament_matches(&tournament, &matches);
+                self.state.tournament.set(Some(tournament));
+            }
+
+            Operation::DecideTournamentMatch {
+                host_chain_id,
+                winner_chain_id,
+            } => {
+                self.decide_tournament_match(None, &host_chain_id, &winner_chain_id);
+            }
+
+            Operation::RegisterTournament {
+                organizer_chain_id,
+                player_name,
+            } => {
+                let organizer: ChainId = organizer_chain_id
+                    .parse()
+                    .expect("Invalid organizer chain ID");
+                let player_chain_id = self.runtime.chain_id();
+                self.state
+                    .tournament_organizer
+                    .set(Some(organizer_chain_id));
+                self.runtime.send_message(
+                    organizer,
+                    CrossChainMessage::TournamentRegister {
+                        player_chain_id,
+                        player_name,
+                    },
+                );
+            }
+
+            Operation::RebalanceMatchmaking => {
+                self.ensure_orchestrator();
+                self.forward_stale_waiters();
+            }
+
+            Operation::UnpublishRoom => {
+                if let Some(published) = self.state.published_room.get().clone() {
+                    if let Ok(orchestrator) = published.orchestrator_chain_id.parse::<ChainId>() {
+                        let host_chain_id = self.runtime.chain_id();
+                        self.runtime.send_message(
+                            orchestrator,
+                            CrossChainMessage::UnlistRoom { host_chain_id },
+                        );
+                    }
+                    self.state.published_room.set(None);
+                }
             }
 
             Operation::RequestFriend { target_chain_id } => {
@@ -315,7 +1441,7 @@ impl Contract for BattleshipContract {
                 if room.status != RoomStatus::Active {
                     panic!("Room not active");
                 }
-                if room.players.len() >= 2 {
+                if room.players.len() >= room.settings.max_players as usize {
                     panic!("Room full");
                 }
                 let friends = self.state.friends.get().clone();
@@ -345,6 +1471,7 @@ impl Contract for BattleshipContract {
             Operation::AcceptInvite {
                 host_chain_id,
                 player_name,
+                stake,
             } => {
                 let mut invitations = self.state.room_invitations.get().clone();
                 if let Some(pos) = invitations
@@ -361,13 +1488,26 @@ impl Contract for BattleshipContract {
                     {
                         let target_chain: ChainId =
                             host_chain_id.parse().expect("Invalid host chain ID");
+                        self.lock_stake(target_chain, stake);
                         let message = CrossChainMessage::JoinRequest {
                             player_chain_id: self.runtime.chain_id(),
                             player_name,
+                            join_code: None,
+                            stake,
                         };
-                        self.runtime.send_message(target_chain, message);
+                        self.state.pending_join.set(Some(host_chain_id));
+                        self.send_tracked(target_chain, message);
+                    }
+                }
+            }
+
+            Operation::SetBotMode { bot_name } => {
+                if let Some(room) = self.state.room.get() {
+                    if room.status == RoomStatus::Active {
+                        panic!("Leave the current room first");
                     }
                 }
+                self.state.bot_name.set(bot_name);
             }
 
             Operation::DeclineInvite { host_chain_id } => {
@@ -384,232 +1524,817 @@ impl Contract for BattleshipContract {
     }
 
     async fn execute_message(&mut self, message: Self::Message) {
+        if self.runtime.message_is_bouncing() == Some(true) {
+            self.handle_bounced_message(message);
+            return;
+        }
         match message {
             CrossChainMessage::JoinRequest {
                 player_chain_id,
                 player_name,
+                join_code,
+                stake,
             } => {
-                let mut room = self.ensure_room_mut();
-                if !self.is_host(&room) {
-                    panic!("Only host can accept joins");
-                }
-                if room.status != RoomStatus::Active {
-                    panic!("Room not active");
-                }
-                if room.players.len() >= 2 {
-                    panic!("Room full");
+                self.authenticate_origin(player_chain_id);
+                self.bot_host_room();
+                let host_chain_id = self.runtime.chain_id();
+                let room = self.state.room.get().clone();
+                if let Some(reason) =
+                    join_rejection(room.as_ref(), &host_chain_id.to_string(), stake)
+                {
+                    if stake == Amount::ZERO {
+                        panic!("{}", reason);
+                    }
+                    // The stake has already reached the escrow here, so reject without bouncing.
+                    self.pay_stake(&player_chain_id.to_string(), stake);
+                    let message = CrossChainMessage::JoinRejected {
+                        host_chain_id,
+                        reason,
+                    };
+                    self.runtime.send_message(player_chain_id, message);
+                    return;
                 }
+                let mut room = self.ensure_room_mut();
                 let mut sent_invites = self.state.sent_invitations.get().clone();
                 let player_str = player_chain_id.to_string();
-                if let Some(pos) = sent_invites.iter().position(|x| x == &player_str) {
-                    sent_invites.remove(pos);
-                    self.state.sent_invitations.set(sent_invites);
+                match sent_invites.iter().position(|x| x == &player_str) {
+                    Some(pos) => {
+                        sent_invites.remove(pos);
+                        self.state.sent_invitations.set(sent_invites);
+                    }
+                    None => {
+                        let code_hash = *self.state.join_code_hash.get();
+                        let checked = check_join_code(
+                            &room.room_id,
+                            code_hash.as_ref(),
+                            join_code.as_deref(),
+                        );
+                        if let Err(reason) = checked {
+                            self.pay_stake(&player_str, stake);
+                            let message = CrossChainMessage::JoinRejected {
+                                host_chain_id,
+                                reason,
+                            };
+                            self.runtime.send_message(player_chain_id, message);
+                            return;
+                        }
+                    }
                 }
                 room.players.push(PlayerInfo {
                     chain_id: player_chain_id.to_string(),
                     name: player_name.clone(),
                     board_submitted: false,
+                    ships_afloat: 0,
+                    weapons_used: Vec::new(),
+                    eliminated: false,
+                    team: None,
+                    series_wins: 0,
                 });
+                if stake > Amount::ZERO {
+                    room.stakes.push(Stake {
+                        chain_id: player_str,
+                        amount: stake,
+                    });
+                }
+                assign_teams(&mut room);
                 room.game_state = GameState::PlacingBoards;
                 self.set_room(room.clone());
 
-                self.runtime.send_message(
+                self.send_tracked(
                     player_chain_id,
                     CrossChainMessage::InitialStateSync { room: room.clone() },
                 );
+                self.sync_room_to_guests(&room, Some(player_chain_id));
+                self.refresh_listing();
+            }
+
+            CrossChainMessage::JoinRejected {
+                host_chain_id,
+                reason,
+            } => {
+                self.authenticate_origin(host_chain_id);
+                if self.state.pending_join.get().as_deref()
+                    != Some(host_chain_id.to_string().as_str())
+                {
+                    return;
+                }
+                self.state.pending_join.set(None);
+                self.state.last_notification.set(Some(format!(
+                    "Join request rejected by {}: {}",
+                    host_chain_id, reason
+                )));
             }
 
             CrossChainMessage::InitialStateSync { room } => {
+                self.authenticate_host(&room);
+                let current = self.state.room.get();
+                if current
+                    .as_ref()
+                    .is_some_and(|r| r.status == RoomStatus::Active)
+                {
+                    panic!("Already in an active room");
+                }
+                let self_chain = self.runtime.chain_id().to_string();
+                if !room.players.iter().any(|p| p.chain_id == self_chain) {
+                    panic!("Not a player in synced room");
+                }
                 self.state.room.set(Some(room.clone()));
+                self.state.pending_join.set(None);
                 self.state
                     .last_notification
                     .set(Some("Room ready".to_string()));
-                if let Some(enemy) = self.find_enemy_chain_id(&room) {
-                    self.ensure_enemy_view_created(&enemy.to_string());
-                }
+                self.ensure_enemy_views_created();
             }
 
             CrossChainMessage::RoomSync { room } => {
+                self.authenticate_host(&room);
+                let previous = self.state.room.get().clone();
+                let Some(current) = &previous else {
+                    panic!("Room sync without a room");
+                };
+                if current.host_chain_id != room.host_chain_id {
+                    panic!("Room sync from a different host");
+                }
+                if current.room_id == room.room_id && current.game_number > room.game_number {
+                    // Sent before the last game of the series ended here.
+                    return;
+                }
+                let started =
+                    room.game_state == GameState::InGame && current.game_state != GameState::InGame;
                 self.state.room.set(Some(room.clone()));
-                if let Some(enemy) = self.find_enemy_chain_id(&room) {
-                    self.ensure_enemy_view_created(&enemy.to_string());
+                self.clear_boards_for_next_game(previous.as_ref());
+                self.ensure_enemy_views_created();
+                if started {
+                    self.share_board_with_teammate(&room);
                 }
             }
 
-            CrossChainMessage::BoardSubmittedNotice { player_chain_id } => {
+            CrossChainMessage::BoardSubmittedNotice {
+                player_chain_id,
+                ship_count,
+            } => {
                 let mut room = self.ensure_room_mut();
                 if !self.is_host(&room) {
                     return;
                 }
+                self.authenticate_player(&room, player_chain_id);
                 if let Some(p) = room
                     .players
                     .iter_mut()
                     .find(|p| p.chain_id == player_chain_id.to_string())
                 {
                     p.board_submitted = true;
+                    p.ships_afloat = ship_count;
+                }
+                self.set_room(room.clone());
+                let host_chain = self.runtime.chain_id();
+                for p in room.players.iter() {
+                    if let Ok(target_chain) = p.chain_id.parse::<ChainId>() {
+                        if target_chain != host_chain {
+                            self.runtime
+                                .send_message(target_chain, CrossChainMessage::RoomSync { room: room.clone() });
+                        }
+                    }
+                }
+                if self.state.bot_name.get().is_some()
+                    && room.players.len() >= 2
+                    && room.players.iter().all(|p| p.board_submitted)
+                {
+                    self.start_game();
+                }
+            }
+
+            CrossChainMessage::AttackRequest {
+                attacker_chain_id,
+                row,
+                col,
+            } => {
+                let mut room = self.ensure_room_mut();
+                self.authenticate_player(&room, attacker_chain_id);
+                if room.game_state != GameState::InGame {
+                    panic!("Game not in progress");
+                }
+                if room.settings.game_mode != GameMode::Classic {
+                    panic!("Room uses salvo attacks");
+                }
+                if room.current_attacker.as_deref() != Some(attacker_chain_id.to_string().as_str())
+                {
+                    panic!("Not attacker's turn");
+                }
+
+                let mut board = self.state.board.get().clone().expect("Board not submitted");
+                let res = apply_attack(&mut board, row, col);
+                if let Err(err) = res {
+                    let defender_chain_id = self.runtime.chain_id();
+                    self.state.last_reveal.set(Some(RevealInfo {
+                        attacker_chain_id: attacker_chain_id.to_string(),
+                        defender_chain_id: defender_chain_id.to_string(),
+                        row,
+                        col,
+                        valid: false,
+                        error: Some(err.clone()),
+                        hit: false,
+                        sunk: false,
+                        mine_hit: false,
+                        sunk_ship_cells: None,
+                        adjacent_coords: None,
+                        shots: Vec::new(),
+                        weapon: None,
+                        sonar_count: None,
+                        next_attacker: attacker_chain_id.to_string(),
+                        game_over: false,
+                        winner_chain_id: None,
+                        timestamp: self.runtime.system_time().micros().to_string(),
+                    }));
+                    self.send_tracked(
+                        attacker_chain_id,
+                        CrossChainMessage::RevealResult {
+                            defender_chain_id,
+                            row,
+                            col,
+                            valid: false,
+                            error: Some(err),
+                            hit: false,
+                            sunk: false,
+                            sunk_ship_cells: None,
+                            adjacent_coords: None,
+                            mine_hit: false,
+                            next_attacker: attacker_chain_id,
+                            game_over: false,
+                            winner_chain_id: None,
+                        },
+                    );
+                    return;
+                }
+
+                let AttackOutcome {
+                    hit,
+                    sunk,
+                    ship_id,
+                    game_over: fleet_destroyed,
+                    mine,
+                } = res.unwrap();
+                let mut sunk_ship_cells = None;
+                let mut adjacent_coords = None;
+                if sunk {
+                    if let Some(ship_id) = ship_id {
+                        let placement_rule = room.settings.placement_rule;
+                        if let Ok((ship_cells, adjacent)) =
+                            apply_sunk_padding(&mut board, ship_id, placement_rule)
+                        {
+                            sunk_ship_cells = Some(ship_cells);
+                            adjacent_coords = Some(adjacent);
+                        }
+                    }
+                }
+                self.state.board.set(Some(board));
+
+                let defender_chain_id = self.runtime.chain_id();
+                if sunk {
+                    record_ship_sunk(&mut room, &defender_chain_id.to_string());
+                }
+                if fleet_destroyed {
+                    eliminate_player(&mut room, &defender_chain_id.to_string());
+                }
+                let game_over = fleet_destroyed && finish_if_last_standing(&mut room);
+                let keeps_turn = attacker_keeps_turn(room.settings.turn_rule, hit, sunk);
+                let next_attacker: ChainId =
+                    advance_turn(&mut room, &attacker_chain_id.to_string(), keeps_turn, mine)
+                        .parse()
+                        .expect("Invalid chain ID");
+                room.current_attacker = Some(next_attacker.to_string());
+                room.pending_attack = None;
+                self.set_room(room.clone());
+                let shot = ShotResult {
+                    row,
+                    col,
+                    hit,
+                    sunk,
+                    mine,
+                    sunk_ship_cells: sunk_ship_cells.clone(),
+                    adjacent_coords: adjacent_coords.clone(),
+                };
+                self.send_turn_sync(&room, attacker_chain_id, std::slice::from_ref(&shot));
+                self.share_board_with_teammate(&room);
+                self.state.last_reveal.set(Some(RevealInfo {
+                    attacker_chain_id: attacker_chain_id.to_string(),
+                    defender_chain_id: defender_chain_id.to_string(),
+                    row,
+                    col,
+                    valid: true,
+                    error: None,
+                    hit,
+                    sunk,
+                    mine_hit: mine,
+                    sunk_ship_cells: sunk_ship_cells.clone(),
+                    adjacent_coords: adjacent_coords.clone(),
+                    shots: vec![shot],
+                    weapon: None,
+                    sonar_count: None,
+                    next_attacker: next_attacker.to_string(),
+                    game_over,
+                    winner_chain_id: room.winner_chain_id.clone(),
+                    timestamp: self.runtime.system_time().micros().to_string(),
+                }));
+
+                self.send_tracked(
+                    attacker_chain_id,
+                    CrossChainMessage::RevealResult {
+                        defender_chain_id,
+                        row,
+                        col,
+                        valid: true,
+                        error: None,
+                        hit,
+                        sunk,
+                        sunk_ship_cells,
+                        adjacent_coords,
+                        mine_hit: mine,
+                        next_attacker,
+                        game_over,
+                        winner_chain_id: room
+                            .winner_chain_id
+                            .as_deref()
+                            .and_then(|c| c.parse().ok()),
+                    },
+                );
+                self.bot_fire();
+            }
+
+            CrossChainMessage::RevealResult {
+                defender_chain_id,
+                row,
+                col,
+                valid,
+                error,
+                hit,
+                sunk,
+                sunk_ship_cells,
+                adjacent_coords,
+                mine_hit,
+                next_attacker,
+                game_over,
+                winner_chain_id,
+            } => {
+                let mut room = self.ensure_room_mut();
+                self.authenticate_player(&room, defender_chain_id);
+                if defender_chain_id == self.runtime.chain_id() {
+                    panic!("Reveal result cannot come from the attacker");
+                }
+                if room.game_state != GameState::InGame && room.game_state != GameState::Ended {
+                    return;
+                }
+                if room.pending_target != Some(defender_chain_id.to_string()) {
+                    return;
+                }
+                if let Some(pending) = room.pending_attack {
+                    if pending.row != row || pending.col != col {
+                        return;
+                    }
+                } else {
+                    return;
+                }
+
+                let shot = ShotResult {
+                    row,
+                    col,
+                    hit,
+                    sunk,
+                    mine: mine_hit,
+                    sunk_ship_cells: sunk_ship_cells.clone(),
+                    adjacent_coords: adjacent_coords.clone(),
+                };
+                let attacker_chain_id = self.runtime.chain_id().to_string();
+                self.state.last_reveal.set(Some(RevealInfo {
+                    attacker_chain_id: attacker_chain_id.clone(),
+                    defender_chain_id: defender_chain_id.to_string(),
+                    row,
+                    col,
+                    valid,
+                    error: error.clone(),
+                    hit,
+                    sunk,
+                    mine_hit,
+                    sunk_ship_cells,
+                    adjacent_coords,
+                    shots: if valid {
+                        vec![shot.clone()]
+                    } else {
+                        Vec::new()
+                    },
+                    weapon: None,
+                    sonar_count: None,
+                    next_attacker: next_attacker.to_string(),
+                    game_over,
+                    winner_chain_id: winner_chain_id.map(|c| c.to_string()),
+                    timestamp: self.runtime.system_time().micros().to_string(),
+                }));
+
+                if valid {
+                    let mut view = self.enemy_view_for(&room, &defender_chain_id.to_string());
+                    record_shot(&mut view, &shot);
+                    self.store_enemy_view(view);
+                    if sunk {
+                        record_ship_sunk(&mut room, &defender_chain_id.to_string());
+                    }
+                    let keeps_turn = attacker_keeps_turn(room.settings.turn_rule, hit, sunk);
+                    advance_turn(&mut room, &attacker_chain_id, keeps_turn, mine_hit);
+                }
+
+                room.current_attacker = Some(next_attacker.to_string());
+                room.pending_attack = None;
+                room.pending_target = None;
+                if game_over {
+                    end_game(&mut room, winner_chain_id.map(|c| c.to_string()));
+                } else if valid && mine_hit && room.settings.mine_effect == MineEffect::RevealCell {
+                    self.detonate_mine(&mut room);
+                }
+                self.set_room(room);
+                self.bot_fire();
+            }
+
+            CrossChainMessage::SalvoAttackRequest {
+                attacker_chain_id,
+                shots,
+            } => {
+                let mut room = self.ensure_room_mut();
+                self.authenticate_player(&room, attacker_chain_id);
+                if room.game_state != GameState::InGame {
+                    panic!("Game not in progress");
+                }
+                if room.settings.game_mode != GameMode::Salvo {
+                    panic!("Room does not use salvo attacks");
+                }
+                if room.current_attacker.as_deref() != Some(attacker_chain_id.to_string().as_str())
+                {
+                    panic!("Not attacker's turn");
+                }
+
+                let mut board = self.state.board.get().clone().expect("Board not submitted");
+                let allowed = salvo_shot_count(&room, &attacker_chain_id.to_string());
+                let res = if shots.len() > allowed as usize {
+                    Err(format!("Salvo allows at most {} shots", allowed))
+                } else {
+                    apply_salvo(&mut board, &shots, room.settings.placement_rule)
+                };
+                let defender_chain_id = self.runtime.chain_id();
+                let first = shots.first().copied().unwrap_or_default();
+
+                let (results, fleet_destroyed) = match res {
+                    Ok(outcome) => outcome,
+                    Err(err) => {
+                        self.state.last_reveal.set(Some(RevealInfo {
+                            attacker_chain_id: attacker_chain_id.to_string(),
+                            defender_chain_id: defender_chain_id.to_string(),
+                            row: first.row,
+                            col: first.col,
+                            valid: false,
+                            error: Some(err.clone()),
+                            hit: false,
+                            sunk: false,
+                            mine_hit: false,
+                            sunk_ship_cells: None,
+                            adjacent_coords: None,
+                            shots: Vec::new(),
+                            weapon: None,
+                            sonar_count: None,
+                            next_attacker: attacker_chain_id.to_string(),
+                            game_over: false,
+                            winner_chain_id: None,
+                            timestamp: self.runtime.system_time().micros().to_string(),
+                        }));
+                        self.send_tracked(
+                            attacker_chain_id,
+                            CrossChainMessage::SalvoRevealResult {
+                                defender_chain_id,
+                                shots: Vec::new(),
+                                valid: false,
+                                error: Some(err),
+                                next_attacker: attacker_chain_id,
+                                game_over: false,
+                                winner_chain_id: None,
+                            },
+                        );
+                        return;
+                    }
+                };
+                self.state.board.set(Some(board));
+
+                for _ in results.iter().filter(|r| r.sunk) {
+                    record_ship_sunk(&mut room, &defender_chain_id.to_string());
+                }
+                if fleet_destroyed {
+                    eliminate_player(&mut room, &defender_chain_id.to_string());
+                }
+                let game_over = fleet_destroyed && finish_if_last_standing(&mut room);
+                let mine_hit = results.iter().any(|r| r.mine);
+                let next_attacker: ChainId =
+                    advance_turn(&mut room, &attacker_chain_id.to_string(), false, mine_hit)
+                        .parse()
+                        .expect("Invalid chain ID");
+                room.current_attacker = Some(next_attacker.to_string());
+                room.pending_salvo = None;
+                self.set_room(room.clone());
+                self.send_turn_sync(&room, attacker_chain_id, &results);
+                self.share_board_with_teammate(&room);
+                self.state.last_reveal.set(Some(RevealInfo {
+                    attacker_chain_id: attacker_chain_id.to_string(),
+                    defender_chain_id: defender_chain_id.to_string(),
+                    row: first.row,
+                    col: first.col,
+                    valid: true,
+                    error: None,
+                    hit: results.iter().any(|r| r.hit),
+                    sunk: results.iter().any(|r| r.sunk),
+                    mine_hit,
+                    sunk_ship_cells: None,
+                    adjacent_coords: None,
+                    shots: results.clone(),
+                    weapon: None,
+                    sonar_count: None,
+                    next_attacker: next_attacker.to_string(),
+                    game_over,
+                    winner_chain_id: room.winner_chain_id.clone(),
+                    timestamp: self.runtime.system_time().micros().to_string(),
+                }));
+
+                self.send_tracked(
+                    attacker_chain_id,
+                    CrossChainMessage::SalvoRevealResult {
+                        defender_chain_id,
+                        shots: results,
+                        valid: true,
+                        error: None,
+                        next_attacker,
+                        game_over,
+                        winner_chain_id: room
+                            .winner_chain_id
+                            .as_deref()
+                            .and_then(|c| c.parse().ok()),
+                    },
+                );
+                self.bot_fire();
+            }
+
+            CrossChainMessage::SalvoRevealResult {
+                defender_chain_id,
+                shots,
+                valid,
+                error,
+                next_attacker,
+                game_over,
+                winner_chain_id,
+            } => {
+                let mut room = self.ensure_room_mut();
+                self.authenticate_player(&room, defender_chain_id);
+                if defender_chain_id == self.runtime.chain_id() {
+                    panic!("Reveal result cannot come from the attacker");
+                }
+                if room.game_state != GameState::InGame && room.game_state != GameState::Ended {
+                    return;
+                }
+                if room.pending_target != Some(defender_chain_id.to_string()) {
+                    return;
+                }
+                let Some(pending) = room.pending_salvo.clone() else {
+                    return;
+                };
+                if valid
+                    && (pending.len() != shots.len()
+                        || pending
+                            .iter()
+                            .zip(&shots)
+                            .any(|(p, s)| p.row != s.row || p.col != s.col))
+                {
+                    return;
+                }
+
+                let first = pending.first().copied().unwrap_or_default();
+                let mine_hits = shots.iter().filter(|r| r.mine).count();
+                let attacker_chain_id = self.runtime.chain_id().to_string();
+                self.state.last_reveal.set(Some(RevealInfo {
+                    attacker_chain_id: attacker_chain_id.clone(),
+                    defender_chain_id: defender_chain_id.to_string(),
+                    row: first.row,
+                    col: first.col,
+                    valid,
+                    error,
+                    hit: shots.iter().any(|r| r.hit),
+                    sunk: shots.iter().any(|r| r.sunk),
+                    mine_hit: mine_hits > 0,
+                    sunk_ship_cells: None,
+                    adjacent_coords: None,
+                    shots: shots.clone(),
+                    weapon: None,
+                    sonar_count: None,
+                    next_attacker: next_attacker.to_string(),
+                    game_over,
+                    winner_chain_id: winner_chain_id.map(|c| c.to_string()),
+                    timestamp: self.runtime.system_time().micros().to_string(),
+                }));
+
+                if valid {
+                    let mut view = self.enemy_view_for(&room, &defender_chain_id.to_string());
+                    for shot in &shots {
+                        record_shot(&mut view, shot);
+                        if shot.sunk {
+                            record_ship_sunk(&mut room, &defender_chain_id.to_string());
+                        }
+                    }
+                    self.store_enemy_view(view);
+                    advance_turn(&mut room, &attacker_chain_id, false, mine_hits > 0);
                 }
-                self.set_room(room.clone());
-                let host_chain = self.runtime.chain_id();
-                for p in room.players.iter() {
-                    if let Ok(target_chain) = p.chain_id.parse::<ChainId>() {
-                        if target_chain != host_chain {
-                            self.runtime
-                                .send_message(target_chain, CrossChainMessage::RoomSync { room: room.clone() });
+
+                room.current_attacker = Some(next_attacker.to_string());
+                room.pending_salvo = None;
+                room.pending_target = None;
+                if game_over {
+                    end_game(&mut room, winner_chain_id.map(|c| c.to_string()));
+                } else if valid && room.settings.mine_effect == MineEffect::RevealCell {
+                    for _ in 0..mine_hits {
+                        if room.status != RoomStatus::Active {
+                            break;
                         }
+                        self.detonate_mine(&mut room);
                     }
                 }
+                self.set_room(room);
+                self.bot_fire();
             }
 
-            CrossChainMessage::AttackRequest {
+            CrossChainMessage::WeaponAttackRequest {
                 attacker_chain_id,
-                row,
-                col,
+                shot,
             } => {
                 let mut room = self.ensure_room_mut();
+                self.authenticate_player(&room, attacker_chain_id);
                 if room.game_state != GameState::InGame {
-                    return;
+                    panic!("Game not in progress");
                 }
-                if room.current_attacker.as_deref() != Some(&attacker_chain_id.to_string()) {
-                    return;
+                if room.current_attacker.as_deref() != Some(attacker_chain_id.to_string().as_str())
+                {
+                    panic!("Not attacker's turn");
                 }
 
                 let mut board = self.state.board.get().clone().expect("Board not submitted");
-                let res = apply_attack(&mut board, row, col);
-                if let Err(err) = res {
-                    let defender_chain_id = self.runtime.chain_id();
-                    self.state.last_reveal.set(Some(RevealInfo {
-                        attacker_chain_id: attacker_chain_id.to_string(),
-                        defender_chain_id: defender_chain_id.to_string(),
-                        row,
-                        col,
-                        valid: false,
-                        error: Some(err.clone()),
-                        hit: false,
-                        sunk: false,
-                        sunk_ship_cells: None,
-                        adjacent_coords: None,
-                        next_attacker: attacker_chain_id.to_string(),
-                        game_over: false,
-                        winner_chain_id: None,
-                        timestamp: self.runtime.system_time().micros().to_string(),
-                    }));
-                    self.runtime.send_message(
-                        attacker_chain_id,
-                        CrossChainMessage::RevealResult {
-                            defender_chain_id,
-                            row,
-                            col,
+                let res = if weapons_remaining(&room, &attacker_chain_id.to_string())
+                    .uses(shot.weapon)
+                    == 0
+                {
+                    Err(format!("No {:?} uses left", shot.weapon))
+                } else {
+                    apply_weapon(&mut board, shot, room.settings.placement_rule)
+                };
+                let defender_chain_id = self.runtime.chain_id();
+
+                let outcome = match res {
+                    Ok(outcome) => outcome,
+                    Err(err) => {
+                        self.state.last_reveal.set(Some(RevealInfo {
+                            attacker_chain_id: attacker_chain_id.to_string(),
+                            defender_chain_id: defender_chain_id.to_string(),
+                            row: shot.row,
+                            col: shot.col,
                             valid: false,
-                            error: Some(err),
+                            error: Some(err.clone()),
                             hit: false,
                             sunk: false,
+                            mine_hit: false,
                             sunk_ship_cells: None,
                             adjacent_coords: None,
-                            next_attacker: attacker_chain_id,
+                            shots: Vec::new(),
+                            weapon: Some(shot.weapon),
+                            sonar_count: None,
+                            next_attacker: attacker_chain_id.to_string(),
                             game_over: false,
                             winner_chain_id: None,
-                        },
-                    );
-                    return;
-                }
-
-                let (hit, sunk, ship_id, game_over) = res.unwrap();
-                let mut sunk_ship_cells = None;
-                let mut adjacent_coords = None;
-                if sunk {
-                    if let Some(ship_id) = ship_id {
-                        if let Ok((ship_cells, adjacent)) = apply_sunk_padding(&mut board, ship_id) {
-                            sunk_ship_cells = Some(ship_cells);
-                            adjacent_coords = Some(adjacent);
-                        }
+                            timestamp: self.runtime.system_time().micros().to_string(),
+                        }));
+                        self.send_tracked(
+                            attacker_chain_id,
+                            CrossChainMessage::WeaponRevealResult {
+                                defender_chain_id,
+                                shot,
+                                valid: false,
+                                error: Some(err),
+                                shots: Vec::new(),
+                                sonar_count: None,
+                                next_attacker: attacker_chain_id,
+                                game_over: false,
+                                winner_chain_id: None,
+                            },
+                        );
+                        return;
                     }
-                }
+                };
                 self.state.board.set(Some(board));
 
-                let defender_chain_id = self.runtime.chain_id();
-                let next_attacker = if hit { attacker_chain_id } else { defender_chain_id };
-                room.current_attacker = Some(next_attacker.to_string());
-                room.pending_attack = None;
-                if game_over {
-                    room.game_state = GameState::Ended;
-                    room.status = RoomStatus::Ended;
-                    room.winner_chain_id = Some(attacker_chain_id.to_string());
+                record_weapon_use(&mut room, &attacker_chain_id.to_string(), shot.weapon);
+                for _ in outcome.shots.iter().filter(|r| r.sunk) {
+                    record_ship_sunk(&mut room, &defender_chain_id.to_string());
+                }
+                if outcome.game_over {
+                    eliminate_player(&mut room, &defender_chain_id.to_string());
                 }
+                let game_over = outcome.game_over && finish_if_last_standing(&mut room);
+                let mine_hit = outcome.shots.iter().any(|r| r.mine);
+                let keeps_turn = weapon_keeps_turn(&room.settings, &outcome.shots);
+                let next_attacker: ChainId = advance_turn(
+                    &mut room,
+                    &attacker_chain_id.to_string(),
+                    keeps_turn,
+                    mine_hit,
+                )
+                .parse()
+                .expect("Invalid chain ID");
+                room.current_attacker = Some(next_attacker.to_string());
+                room.pending_weapon = None;
                 self.set_room(room.clone());
+                self.send_turn_sync(&room, attacker_chain_id, &outcome.shots);
+                self.share_board_with_teammate(&room);
                 self.state.last_reveal.set(Some(RevealInfo {
                     attacker_chain_id: attacker_chain_id.to_string(),
                     defender_chain_id: defender_chain_id.to_string(),
-                    row,
-                    col,
+                    row: shot.row,
+                    col: shot.col,
                     valid: true,
                     error: None,
-                    hit,
-                    sunk,
-                    sunk_ship_cells: sunk_ship_cells.clone(),
-                    adjacent_coords: adjacent_coords.clone(),
+                    hit: outcome.shots.iter().any(|r| r.hit),
+                    sunk: outcome.shots.iter().any(|r| r.sunk),
+                    mine_hit,
+                    sunk_ship_cells: None,
+                    adjacent_coords: None,
+                    shots: outcome.shots.clone(),
+                    weapon: Some(shot.weapon),
+                    sonar_count: outcome.sonar_count,
                     next_attacker: next_attacker.to_string(),
                     game_over,
-                    winner_chain_id: if game_over { Some(attacker_chain_id.to_string()) } else { None },
+                    winner_chain_id: room.winner_chain_id.clone(),
                     timestamp: self.runtime.system_time().micros().to_string(),
                 }));
 
-                self.runtime.send_message(
+                self.send_tracked(
                     attacker_chain_id,
-                    CrossChainMessage::RevealResult {
+                    CrossChainMessage::WeaponRevealResult {
                         defender_chain_id,
-                        row,
-                        col,
+                        shot,
                         valid: true,
                         error: None,
-                        hit,
-                        sunk,
-                        sunk_ship_cells,
-                        adjacent_coords,
+                        shots: outcome.shots,
+                        sonar_count: outcome.sonar_count,
                         next_attacker,
                         game_over,
-                        winner_chain_id: if game_over { Some(attacker_chain_id) } else { None },
+                        winner_chain_id: room
+                            .winner_chain_id
+                            .as_deref()
+                            .and_then(|c| c.parse().ok()),
                     },
                 );
+                self.bot_fire();
             }
 
-            CrossChainMessage::RevealResult {
+            CrossChainMessage::WeaponRevealResult {
                 defender_chain_id,
-                row,
-                col,
+                shot,
                 valid,
                 error,
-                hit,
-                sunk,
-                sunk_ship_cells,
-                adjacent_coords,
+                shots,
+                sonar_count,
                 next_attacker,
                 game_over,
                 winner_chain_id,
             } => {
                 let mut room = self.ensure_room_mut();
+                self.authenticate_player(&room, defender_chain_id);
+                if defender_chain_id == self.runtime.chain_id() {
+                    panic!("Reveal result cannot come from the attacker");
+                }
                 if room.game_state != GameState::InGame && room.game_state != GameState::Ended {
                     return;
                 }
-                if let Some(pending) = room.pending_attack {
-                    if pending.row != row || pending.col != col {
-                        return;
-                    }
-                } else {
+                if room.pending_weapon != Some(shot)
+                    || room.pending_target != Some(defender_chain_id.to_string())
+                {
                     return;
                 }
 
+                let mine_hits = shots.iter().filter(|r| r.mine).count();
                 let attacker_chain_id = self.runtime.chain_id().to_string();
                 self.state.last_reveal.set(Some(RevealInfo {
-                    attacker_chain_id,
+                    attacker_chain_id: attacker_chain_id.clone(),
                     defender_chain_id: defender_chain_id.to_string(),
-                    row,
-                    col,
+                    row: shot.row,
+                    col: shot.col,
                     valid,
-                    error: error.clone(),
-                    hit,
-                    sunk,
-                    sunk_ship_cells: sunk_ship_cells.clone(),
-                    adjacent_coords: adjacent_coords.clone(),
+                    error,
+                    hit: shots.iter().any(|r| r.hit),
+                    sunk: shots.iter().any(|r| r.sunk),
+                    mine_hit: mine_hits > 0,
+                    sunk_ship_cells: None,
+                    adjacent_coords: None,
+                    shots: shots.clone(),
+                    weapon: Some(shot.weapon),
+                    sonar_count,
                     next_attacker: next_attacker.to_string(),
                     game_over,
                     winner_chain_id: winner_chain_id.map(|c| c.to_string()),
@@ -617,68 +2342,163 @@ impl Contract for BattleshipContract {
                 }));
 
                 if valid {
-                    let mut view = self
-                        .state
-                        .enemy_view
-                        .get()
-                        .clone()
-                        .unwrap_or_else(|| empty_enemy_view(10));
-                    if sunk {
-                        set_enemy_view_cell(&mut view, row, col, EnemyCell::Sunk).ok();
-                        if let Some(cells) = sunk_ship_cells.as_ref() {
-                            for c in cells {
-                                set_enemy_view_cell(&mut view, c.row, c.col, EnemyCell::Sunk).ok();
-                            }
+                    let mut view = self.enemy_view_for(&room, &defender_chain_id.to_string());
+                    for result in &shots {
+                        record_shot(&mut view, result);
+                        if result.sunk {
+                            record_ship_sunk(&mut room, &defender_chain_id.to_string());
                         }
-                        if let Some(adj) = adjacent_coords.as_ref() {
-                            for c in adj {
-                                let idx = (c.row as usize) * (view.size as usize) + (c.col as usize);
-                                if idx < view.cells.len() && view.cells[idx] == EnemyCell::Unknown {
-                                    set_enemy_view_cell(&mut view, c.row, c.col, EnemyCell::Miss).ok();
-                                }
-                            }
-                        }
-                    } else if hit {
-                        set_enemy_view_cell(&mut view, row, col, EnemyCell::Hit).ok();
-                    } else {
-                        set_enemy_view_cell(&mut view, row, col, EnemyCell::Miss).ok();
                     }
-                    self.state.enemy_view.set(Some(view));
+                    self.store_enemy_view(view);
+                    record_weapon_use(&mut room, &attacker_chain_id, shot.weapon);
+                    let keeps_turn = weapon_keeps_turn(&room.settings, &shots);
+                    advance_turn(&mut room, &attacker_chain_id, keeps_turn, mine_hits > 0);
                 }
 
                 room.current_attacker = Some(next_attacker.to_string());
-                room.pending_attack = None;
+                room.pending_weapon = None;
+                room.pending_target = None;
                 if game_over {
-                    room.game_state = GameState::Ended;
-                    room.status = RoomStatus::Ended;
-                    room.winner_chain_id = winner_chain_id.map(|c| c.to_string());
+                    end_game(&mut room, winner_chain_id.map(|c| c.to_string()));
+                } else if valid && room.settings.mine_effect == MineEffect::RevealCell {
+                    for _ in 0..mine_hits {
+                        if room.status != RoomStatus::Active {
+                            break;
+                        }
+                        self.detonate_mine(&mut room);
+                    }
                 }
-                let room_id = room.room_id.clone();
-                self.set_room(room.clone());
+                self.set_room(room);
+            }
 
-                if !valid {
-                    return;
+            CrossChainMessage::MineBlast {
+                player_chain_id,
+                shot,
+                fleet_destroyed,
+            } => {
+                let mut room = self.ensure_room_mut();
+                self.authenticate_player(&room, player_chain_id);
+                if player_chain_id == self.runtime.chain_id() {
+                    panic!("Mine blast cannot come from the mine owner");
                 }
-
-                let _ = defender_chain_id;
-                let _ = room_id;
+                let mut view = self.enemy_view_for(&room, &player_chain_id.to_string());
+                record_shot(&mut view, &shot);
+                self.store_enemy_view(view);
+                if shot.sunk {
+                    record_ship_sunk(&mut room, &player_chain_id.to_string());
+                }
+                if fleet_destroyed && room.status == RoomStatus::Active {
+                    eliminate_player(&mut room, &player_chain_id.to_string());
+                    finish_if_last_standing(&mut room);
+                }
+                self.set_room(room);
+                self.state.last_notification.set(Some(format!(
+                    "Mine blast revealed enemy cell ({},{})",
+                    shot.row, shot.col
+                )));
             }
 
             CrossChainMessage::LeaveNotice { player_chain_id } => {
                 let mut room = self.ensure_room_mut();
+                self.authenticate_player(&room, player_chain_id);
                 if room.status != RoomStatus::Active {
                     return;
                 }
-                let winner_chain_id = self.runtime.chain_id().to_string();
-                room.status = RoomStatus::Ended;
-                room.game_state = GameState::Ended;
-                room.winner_chain_id = Some(winner_chain_id.clone());
-                let room_id = room.room_id.clone();
+                let leaver = player_chain_id.to_string();
+                let host_left =
+                    room.host_chain_id == leaver && room.game_state != GameState::InGame;
+                if !room_started(&room) {
+                    // Leaving before the first game is not a forfeit.
+                    if let Some(stake) = take_stake(&mut room, &leaver) {
+                        self.pay_stake(&leaver, stake);
+                    }
+                }
+                remove_player(&mut room, &leaver);
+                if !finish_if_last_standing(&mut room) && host_left {
+                    // Nobody else can start the game without the host.
+                    room.status = RoomStatus::Ended;
+                    room.game_state = GameState::Ended;
+                }
+                self.set_room(room);
+                self.refresh_listing();
+            }
+
+            CrossChainMessage::TimeoutClaim {
+                claimant_chain_id,
+                stalled_chain_id,
+            } => {
+                let mut room = self.ensure_room_mut();
+                self.authenticate_player(&room, claimant_chain_id);
+                let stalled = stalled_chain_id.to_string();
+                if let Err(err) =
+                    accept_timeout_claim(&mut room, &claimant_chain_id.to_string(), &stalled)
+                {
+                    panic!("{}", err);
+                }
+                self.set_room(room);
+                self.state
+                    .last_notification
+                    .set(Some(format!("{} timed out", stalled)));
+                self.bot_fire();
+            }
+
+            CrossChainMessage::DrawOffered { player_chain_id } => {
+                self.receive_vote_to_end(player_chain_id, Proposal::Draw, false)
+            }
+            CrossChainMessage::DrawAccepted { player_chain_id } => {
+                self.receive_vote_to_end(player_chain_id, Proposal::Draw, true)
+            }
+            CrossChainMessage::AbortProposed { player_chain_id } => {
+                self.receive_vote_to_end(player_chain_id, Proposal::Abort, false)
+            }
+            CrossChainMessage::AbortAccepted { player_chain_id } => {
+                self.receive_vote_to_end(player_chain_id, Proposal::Abort, true)
+            }
+
+            CrossChainMessage::TurnSync {
+                defender_chain_id,
+                attacker_chain_id,
+                shots,
+                room: synced,
+            } => {
+                let mut room = self.ensure_room_mut();
+                self.authenticate_player(&room, defender_chain_id);
+                if synced.room_id != room.room_id || synced.game_number != room.game_number {
+                    return;
+                }
+                let self_chain = self.runtime.chain_id().to_string();
+                if teammate_of(&room, &self_chain) == Some(attacker_chain_id.to_string()) {
+                    let mut view = self.enemy_view_for(&room, &defender_chain_id.to_string());
+                    for shot in &shots {
+                        record_shot(&mut view, shot);
+                    }
+                    self.store_enemy_view(view);
+                }
+                // Only the shared turn state is taken over; pending attacks stay local.
+                room.players = synced.players;
+                room.current_attacker = synced.current_attacker;
+                room.skip_next_turn = synced.skip_next_turn;
+                room.game_state = synced.game_state;
+                room.status = synced.status;
+                room.winner_chain_id = synced.winner_chain_id;
                 self.set_room(room);
-                let _ = (player_chain_id, room_id);
+            }
+
+            CrossChainMessage::TeammateBoard {
+                player_chain_id,
+                board,
+            } => {
+                let room = self.ensure_room_mut();
+                self.authenticate_player(&room, player_chain_id);
+                let self_chain = self.runtime.chain_id().to_string();
+                if teammate_of(&room, &self_chain) != Some(player_chain_id.to_string()) {
+                    panic!("Board shared by a player outside the team");
+                }
+                self.state.teammate_board.set(Some(board));
             }
 
             CrossChainMessage::FriendRequest { requester_chain_id } => {
+                self.authenticate_origin(requester_chain_id);
                 let requester_str = requester_chain_id.to_string();
                 let friends = self.state.friends.get().clone();
                 if friends.contains(&requester_str) {
@@ -692,6 +2512,7 @@ impl Contract for BattleshipContract {
             }
 
             CrossChainMessage::FriendAccepted { target_chain_id } => {
+                self.authenticate_origin(target_chain_id);
                 let target_str = target_chain_id.to_string();
                 let mut friends = self.state.friends.get().clone();
                 if !friends.contains(&target_str) {
@@ -706,6 +2527,7 @@ impl Contract for BattleshipContract {
             }
 
             CrossChainMessage::RoomInvitation { host_chain_id, timestamp } => {
+                self.authenticate_origin(host_chain_id);
                 let host_str = host_chain_id.to_string();
                 let mut invitations = self.state.room_invitations.get().clone();
                 if !invitations.iter().any(|inv| inv.host_chain_id == host_str) {
@@ -718,6 +2540,7 @@ impl Contract for BattleshipContract {
             }
 
             CrossChainMessage::RoomInvitationCancelled { host_chain_id } => {
+                self.authenticate_origin(host_chain_id);
                 let host_str = host_chain_id.to_string();
                 let mut invitations = self.state.room_invitations.get().clone();
                 if let Some(pos) = invitations.iter().position(|inv| inv.host_chain_id == host_str) {
@@ -729,105 +2552,237 @@ impl Contract for BattleshipContract {
             CrossChainMessage::MatchmakingEnqueue {
                 player_chain_id,
                 player_name,
+                preferences,
+                hops,
             } => {
+                if hops == 0 {
+                    self.authenticate_origin(player_chain_id);
+                } else {
+                    self.authenticate_orchestrator();
+                }
+                self.ensure_orchestrator();
                 let mut queue = self.state.matchmaking_queue.get().clone();
                 let player_chain_str = player_chain_id.to_string();
-                if !queue.iter().any(|p| p.chain_id == player_chain_str) {
-                    queue.push(MatchmakingPlayer {
-                        chain_id: player_chain_str,
-                        player_name: player_name.clone(),
-                    });
-                    self.state.matchmaking_queue.set(queue.clone());
-                }
-
-                let orchestrator_chain_id = self.runtime.chain_id();
-                self.runtime.send_message(
-                    player_chain_id,
-                    CrossChainMessage::MatchmakingEnqueued {
-                        orchestrator_chain_id,
-                    },
-                );
+                // A repeated search replaces the earlier one.
+                queue.retain(|p| p.chain_id != player_chain_str);
+                let now = self.runtime.system_time().micros();
+                let player = MatchmakingPlayer {
+                    chain_id: player_chain_str,
+                    player_name: player_name.clone(),
+                    preferences,
+                    enqueued_at: now,
+                    hops,
+                };
 
-                if queue.len() < 2 {
+                // The longest-waiting compatible player hosts.
+                let Some(pos) = queue
+                    .iter()
+                    .position(|p| preferences_compatible(&p.preferences, &player.preferences))
+                else {
+                    queue.push(player);
+                    self.state.matchmaking_queue.set(queue.clone());
+                    self.send_queue_status(&queue, queue.len() - 1);
+                    self.forward_stale_waiters();
                     return;
-                }
-
-                let host = queue.remove(0);
-                let guest = queue.remove(0);
-                self.state.matchmaking_queue.set(queue);
+                };
+                let host = queue.remove(pos);
+                let guest = player;
+                self.state.matchmaking_queue.set(queue.clone());
+                let mut waits = self.state.recent_match_waits.get().clone();
+                record_match_wait(&mut waits, now.saturating_sub(host.enqueued_at));
+                self.state.recent_match_waits.set(waits);
+                self.send_queue_status(&queue, pos);
+                let settings = match_settings(&host.preferences, &guest.preferences);
 
                 let host_chain_id: ChainId = host.chain_id.parse().expect("Invalid host chain ID");
                 let guest_chain_id: ChainId =
                     guest.chain_id.parse().expect("Invalid guest chain ID");
-                self.runtime.send_message(
+                // Tracked, so that a busy host bounces it back and the guest learns the match fell through.
+                self.send_tracked(
                     host_chain_id,
                     CrossChainMessage::MatchmakingStart {
                         host_name: host.player_name,
                         guest_chain_id,
                         guest_name: guest.player_name,
+                        settings,
                     },
                 );
                 self.runtime.send_message(
                     guest_chain_id,
                     CrossChainMessage::MatchmakingFound { host_chain_id },
                 );
+                self.forward_stale_waiters();
             }
 
             CrossChainMessage::MatchmakingEnqueued {
                 orchestrator_chain_id,
+                position,
+                queue_size,
+                estimated_wait_secs,
             } => {
+                self.authenticate_origin(orchestrator_chain_id);
+                self.authenticate_orchestrator();
                 self.state.last_notification.set(Some(format!(
-                    "Enqueued on {}",
-                    orchestrator_chain_id
+                    "Enqueued on {} ({} of {})",
+                    orchestrator_chain_id, position, queue_size
                 )));
+                self.state.matchmaking_status.set(Some(QueueStatus {
+                    orchestrator_chain_id: orchestrator_chain_id.to_string(),
+                    position,
+                    queue_size,
+                    estimated_wait_secs,
+                }));
             }
 
             CrossChainMessage::MatchmakingStart {
                 host_name,
                 guest_chain_id,
                 guest_name,
+                settings,
             } => {
-                if let Some(room) = self.state.room.get().clone() {
-                    if room.status == RoomStatus::Active {
-                        return;
+                self.authenticate_orchestrator();
+                self.host_match(host_name, guest_chain_id, guest_name, settings);
+                self.state.matchmaking_status.set(None);
+                self.state
+                    .last_notification
+                    .set(Some("Match found (host)".to_string()));
+            }
+
+            CrossChainMessage::TournamentRegister {
+                player_chain_id,
+                player_name,
+            } => {
+                self.authenticate_origin(player_chain_id);
+                let organizer_chain_id = self.runtime.chain_id();
+                let (tournament_id, error) = match self.state.tournament.get().clone() {
+                    Some(mut tournament) => {
+                        let result = register_tournament_player(
+                            &mut tournament,
+                            &player_chain_id.to_string(),
+                            player_name,
+                        );
+                        let tournament_id = tournament.tournament_id.clone();
+                        if result.is_ok() {
+                            self.state.tournament.set(Some(tournament));
+                        }
+                        (tournament_id, result.err())
                     }
-                }
+                    None => (
+                        String::new(),
+                        Some("No tournament organized here".to_string()),
+                    ),
+                };
+                self.runtime.send_message(
+                    player_chain_id,
+                    CrossChainMessage::TournamentRegistered {
+                        organizer_chain_id,
+                        tournament_id,
+                        error,
+                    },
+                );
+            }
 
-                let chain_id = self.runtime.chain_id().to_string();
-                let room_id = self.runtime.system_time().micros().to_string();
-                let room = Room {
-                    room_id,
-                    host_chain_id: chain_id.clone(),
-                    status: RoomStatus::Active,
-                    game_state: GameState::PlacingBoards,
-                    players: vec![
-                        PlayerInfo {
-                            chain_id: chain_id.clone(),
-                            name: host_name,
-                            board_submitted: false,
-                        },
-                        PlayerInfo {
-                            chain_id: guest_chain_id.to_string(),
-                            name: guest_name,
-                            board_submitted: false,
-                        },
-                    ],
-                    current_attacker: None,
-                    pending_attack: None,
-                    winner_chain_id: None,
+            CrossChainMessage::TournamentRegistered {
+                organizer_chain_id,
+                tournament_id,
+                error,
+            } => {
+                self.authenticate_origin(organizer_chain_id);
+                if self.state.tournament_organizer.get().as_deref()
+                    != Some(organizer_chain_id.to_string().as_str())
+                {
+                    return;
+                }
+                let notification = match error {
+                    Some(error) => {
+                        self.state.tournament_organizer.set(None);
+                        format!("Tournament registration rejected: {}", error)
+                    }
+                    None => format!("Registered for tournament {}", tournament_id),
                 };
-                self.state.board.set(None);
-                self.state.enemy_view.set(None);
-                self.set_room(room.clone());
-                self.state.last_reveal.set(None);
+                self.state.last_notification.set(Some(notification));
+            }
+
+            CrossChainMessage::TournamentMatchStart {
+                tournament_id,
+                round,
+                host_name,
+                guest_chain_id,
+                guest_name,
+                settings,
+            } => {
+                let organizer_chain_id = self.authenticate_organizer();
+                let room = self.host_match(host_name, guest_chain_id, guest_name, settings);
+                self.state.tournament_match.set(Some(TournamentMatchRef {
+                    organizer_chain_id,
+                    tournament_id,
+                    round,
+                    room_id: room.room_id,
+                }));
                 self.state
                     .last_notification
-                    .set(Some("Match found (host)".to_string()));
-                self.ensure_enemy_view_created(&guest_chain_id.to_string());
-                self.runtime.send_message(guest_chain_id, CrossChainMessage::InitialStateSync { room });
+                    .set(Some(format!("Tournament round {} match (host)", round)));
+            }
+
+            CrossChainMessage::TournamentMatchFound { host_chain_id } => {
+                self.authenticate_organizer();
+                self.state.last_notification.set(Some(format!(
+                    "Tournament match found. Host: {}",
+                    host_chain_id
+                )));
+            }
+
+            CrossChainMessage::TournamentResult {
+                tournament_id,
+                round,
+                host_chain_id,
+                winner_chain_id,
+            } => {
+                self.authenticate_origin(host_chain_id);
+                self.decide_tournament_match(
+                    Some((&tournament_id, round)),
+                    &host_chain_id.to_string(),
+                    &winner_chain_id.to_string(),
+                );
+            }
+
+            CrossChainMessage::ListRoom { listing } => {
+                let host_chain_id: ChainId = listing
+                    .host_chain_id
+                    .parse()
+                    .expect("Invalid host chain ID");
+                self.authenticate_origin(host_chain_id);
+                self.ensure_orchestrator();
+                let mut open_rooms = self.state.open_rooms.get().clone();
+                match open_rooms
+                    .iter()
+                    .position(|l| l.host_chain_id == listing.host_chain_id)
+                {
+                    Some(pos) => open_rooms[pos] = listing,
+                    None => open_rooms.push(listing),
+                }
+                self.state.open_rooms.set(open_rooms);
+            }
+
+            CrossChainMessage::UnlistRoom { host_chain_id } => {
+                self.authenticate_origin(host_chain_id);
+                let host = host_chain_id.to_string();
+                let mut open_rooms = self.state.open_rooms.get().clone();
+                open_rooms.retain(|l| l.host_chain_id != host);
+                self.state.open_rooms.set(open_rooms);
+            }
+
+            CrossChainMessage::MatchmakingCancelled { host_chain_id } => {
+                self.authenticate_orchestrator();
+                self.state.last_notification.set(Some(format!(
+                    "Match with {} fell through: the host is busy, search again",
+                    host_chain_id
+                )));
             }
 
             CrossChainMessage::MatchmakingFound { host_chain_id } => {
+                self.authenticate_orchestrator();
+                self.state.matchmaking_status.set(None);
                 self.state.last_notification.set(Some(format!(
                     "Match found. Host: {}",
                     host_chain_id
@@ -1,5 +1,7 @@
 use async_graphql::{Request, Response};
-use linera_sdk::linera_base_types::{ChainId, ContractAbi, ServiceAbi};
+use linera_sdk::linera_base_types::{
+    Amount, BcsHashable, ChainId, ContractAbi, CryptoHash, ServiceAbi,
+};
 use serde::{Deserialize, Serialize};
 
 pub struct BattleshipAbi;
@@ -18,6 +20,8 @@ impl ServiceAbi for BattleshipAbi {
 pub enum RoomStatus {
     Active,
     Ended,
+    /// Ended early by agreement of every player, without a winner.
+    Aborted,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, async_graphql::Enum)]
@@ -34,8 +38,265 @@ pub enum Axis {
     Vert,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, async_graphql::SimpleObject)]
+#[derive(
+    Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, async_graphql::Enum,
+)]
+pub enum GameMode {
+    #[default]
+    Classic,
+    Salvo,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, async_graphql::Enum,
+)]
+pub enum TurnRule {
+    #[default]
+    ExtraShotOnHit,
+    StrictAlternate,
+    ExtraShotOnSink,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, async_graphql::Enum,
+)]
+pub enum PlacementRule {
+    #[default]
+    NoTouch,
+    DiagonalTouch,
+    AnyTouch,
+}
+
+const ALL_NEIGHBOURS: [(i8, i8); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+const EDGE_NEIGHBOURS: [(i8, i8); 4] = [(-1, 0), (0, -1), (0, 1), (1, 0)];
+
+impl PlacementRule {
+    /// Offsets around a ship cell that may not hold another ship, and are therefore
+    /// known to be empty once the ship is sunk.
+    pub fn blocked_neighbours(self) -> &'static [(i8, i8)] {
+        match self {
+            PlacementRule::NoTouch => &ALL_NEIGHBOURS,
+            PlacementRule::DiagonalTouch => &EDGE_NEIGHBOURS,
+            PlacementRule::AnyTouch => &[],
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, async_graphql::Enum,
+)]
+pub enum Rotation {
+    #[default]
+    R0,
+    R90,
+    R180,
+    R270,
+}
+
+impl Rotation {
+    fn quarter_turns(self) -> u8 {
+        match self {
+            Rotation::R0 => 0,
+            Rotation::R90 => 1,
+            Rotation::R180 => 2,
+            Rotation::R270 => 3,
+        }
+    }
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    async_graphql::SimpleObject,
+    async_graphql::InputObject,
+)]
+#[graphql(rename_fields = "camelCase", input_name = "ShapeDefinitionInput")]
+pub struct ShapeDefinition {
+    pub shape_id: String,
+    /// Cell offsets of the shape in its unrotated orientation.
+    pub cells: Vec<Coord>,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, async_graphql::Enum,
+)]
+pub enum MineEffect {
+    #[default]
+    SkipTurn,
+    RevealCell,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, async_graphql::Enum)]
+pub enum Weapon {
+    /// Counts the ship cells in a 3x3 area without attacking it.
+    Sonar,
+    /// Attacks the target cell and its four edge neighbours.
+    CrossBomb,
+    /// Travels east along the row from the target cell and stops at the first ship or mine.
+    Torpedo,
+}
+
+/// Uses of each special weapon a player gets per game.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    async_graphql::SimpleObject,
+    async_graphql::InputObject,
+)]
+#[graphql(rename_fields = "camelCase", input_name = "WeaponLoadoutInput")]
+pub struct WeaponLoadout {
+    #[graphql(default = 1)]
+    pub sonar: u8,
+    #[graphql(default = 1)]
+    pub cross_bomb: u8,
+    #[graphql(default = 1)]
+    pub torpedo: u8,
+}
+
+impl Default for WeaponLoadout {
+    fn default() -> Self {
+        WeaponLoadout {
+            sonar: 1,
+            cross_bomb: 1,
+            torpedo: 1,
+        }
+    }
+}
+
+impl WeaponLoadout {
+    pub fn uses(self, weapon: Weapon) -> u8 {
+        match weapon {
+            Weapon::Sonar => self.sonar,
+            Weapon::CrossBomb => self.cross_bomb,
+            Weapon::Torpedo => self.torpedo,
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, async_graphql::SimpleObject,
+)]
 #[graphql(rename_fields = "camelCase")]
+pub struct WeaponShot {
+    pub weapon: Weapon,
+    pub row: u8,
+    pub col: u8,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    async_graphql::SimpleObject,
+    async_graphql::InputObject,
+)]
+#[graphql(rename_fields = "camelCase", input_name = "RoomSettingsInput")]
+pub struct RoomSettings {
+    /// Seats in the room: 2 for a duel, up to 4 for a free-for-all.
+    #[graphql(default = 2)]
+    pub max_players: u8,
+    /// Splits a four-player room into two teams of two, seated alternately.
+    #[graphql(default)]
+    pub teams: bool,
+    #[graphql(default)]
+    pub game_mode: GameMode,
+    /// Shots per salvo turn. `None` means one shot per surviving ship.
+    pub salvo_shots: Option<u8>,
+    /// Whether a classic shot lets the attacker fire again. Salvo turns always alternate.
+    #[graphql(default)]
+    pub turn_rule: TurnRule,
+    #[graphql(default)]
+    pub placement_rule: PlacementRule,
+    /// Room-specific polyominoes, usable next to the built-in shapes.
+    #[graphql(default)]
+    pub custom_shapes: Vec<ShapeDefinition>,
+    /// Cells that exist on every board of the room and can neither hold ships nor be shot.
+    #[graphql(default)]
+    pub islands: Vec<Coord>,
+    #[graphql(default)]
+    pub mines_per_player: u8,
+    #[graphql(default)]
+    pub mine_effect: MineEffect,
+    #[graphql(default)]
+    pub weapons: WeaponLoadout,
+    /// Sizes (in cells) of the ships every board must hold. Empty leaves the fleet free.
+    #[graphql(default)]
+    pub fleet: Vec<u8>,
+    /// Whether players may ask for a `targetHeatmap` while the game runs; turn off for ranked rooms.
+    #[graphql(default = true)]
+    pub target_hints: bool,
+    #[graphql(default)]
+    pub ranked: bool,
+    /// Turn time limit in seconds; once it runs out, another player can `claimTimeout` the player
+    /// the game is waiting on.
+    pub turn_seconds: Option<u32>,
+    /// Games in the series: 1, 3, 5 or 7. The room ends once the series is decided.
+    #[graphql(default = 1)]
+    pub best_of: u8,
+    /// Native tokens every player locks with the host; the winning side takes the pot.
+    #[graphql(default)]
+    pub stake: Amount,
+}
+
+impl Default for RoomSettings {
+    fn default() -> Self {
+        RoomSettings {
+            max_players: 2,
+            teams: false,
+            game_mode: GameMode::default(),
+            salvo_shots: None,
+            turn_rule: TurnRule::default(),
+            placement_rule: PlacementRule::default(),
+            custom_shapes: Vec::new(),
+            islands: Vec::new(),
+            mines_per_player: 0,
+            mine_effect: MineEffect::default(),
+            weapons: WeaponLoadout::default(),
+            fleet: Vec::new(),
+            target_hints: true,
+            ranked: false,
+            turn_seconds: None,
+            best_of: 1,
+            stake: Amount::ZERO,
+        }
+    }
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    Hash,
+    async_graphql::SimpleObject,
+    async_graphql::InputObject,
+)]
+#[graphql(rename_fields = "camelCase", input_name = "CoordInput")]
 pub struct Coord {
     pub row: u8,
     pub col: u8,
@@ -47,6 +308,14 @@ pub struct PlayerInfo {
     pub chain_id: String,
     pub name: String,
     pub board_submitted: bool,
+    pub ships_afloat: u8,
+    pub weapons_used: Vec<Weapon>,
+    /// Set once the player's fleet is destroyed or they leave a running game.
+    pub eliminated: bool,
+    /// Team index (0 or 1) in team rooms.
+    pub team: Option<u8>,
+    /// Games won in the room's series; teammates share their team's wins.
+    pub series_wins: u8,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, async_graphql::SimpleObject)]
@@ -56,10 +325,49 @@ pub struct Room {
     pub host_chain_id: String,
     pub status: RoomStatus,
     pub game_state: GameState,
+    pub settings: RoomSettings,
+    /// Joining needs the host's join code, unless the host invited the player.
+    pub private: bool,
     pub players: Vec<PlayerInfo>,
     pub current_attacker: Option<String>,
     pub pending_attack: Option<Coord>,
+    pub pending_salvo: Option<Vec<Coord>>,
+    pub pending_weapon: Option<WeaponShot>,
+    /// Opponent targeted by the pending attack, salvo or weapon.
+    pub pending_target: Option<String>,
+    /// Players who hit a mine and lose their next turn.
+    pub skip_next_turn: Vec<String>,
+    /// Winner of the room, or of the last game while a series is still running.
     pub winner_chain_id: Option<String>,
+    /// Current game of the series, from 1.
+    pub game_number: u8,
+    /// Stakes escrowed by the host, emptied once they are paid out.
+    pub stakes: Vec<Stake>,
+    /// Players who agreed to end the current game in a draw.
+    pub draw_votes: Vec<String>,
+    /// Players who agreed to abort the room.
+    pub abort_votes: Vec<String>,
+    /// When this chain last saw the room change, in microseconds; stalled players can be timed out.
+    pub last_activity_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, async_graphql::SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+pub struct Stake {
+    pub chain_id: String,
+    pub amount: Amount,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, async_graphql::SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+pub struct ShotResult {
+    pub row: u8,
+    pub col: u8,
+    pub hit: bool,
+    pub sunk: bool,
+    pub mine: bool,
+    pub sunk_ship_cells: Option<Vec<Coord>>,
+    pub adjacent_coords: Option<Vec<Coord>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, async_graphql::SimpleObject)]
@@ -73,8 +381,13 @@ pub struct RevealInfo {
     pub error: Option<String>,
     pub hit: bool,
     pub sunk: bool,
+    pub mine_hit: bool,
     pub sunk_ship_cells: Option<Vec<Coord>>,
     pub adjacent_coords: Option<Vec<Coord>>,
+    pub shots: Vec<ShotResult>,
+    pub weapon: Option<Weapon>,
+    /// Ship cells found by a sonar sweep.
+    pub sonar_count: Option<u8>,
     pub next_attacker: String,
     pub game_over: bool,
     pub winner_chain_id: Option<String>,
@@ -94,13 +407,37 @@ pub enum EnemyCell {
     Miss,
     Hit,
     Sunk,
+    Island,
+    Mine,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, async_graphql::SimpleObject)]
 #[graphql(rename_fields = "camelCase")]
 pub struct EnemyBoardView {
+    pub owner_chain_id: String,
     pub size: u8,
     pub cells: Vec<EnemyCell>,
+    /// Sizes of the ships sunk on this board so far, in sinking order.
+    pub sunk_ships: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, async_graphql::SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+pub struct EnemyFleetStatus {
+    pub owner_chain_id: String,
+    pub sunk: Vec<u8>,
+    /// Ship sizes still afloat; `None` when the room has no fleet rule.
+    pub remaining: Option<Vec<u8>>,
+}
+
+/// Per-cell weights, row-major like `EnemyBoardView::cells`: the number of ways a remaining ship can
+/// cover the cell, with placements through unresolved hits counting more.
+#[derive(Debug, Clone, Serialize, Deserialize, async_graphql::SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+pub struct TargetHeatmap {
+    pub owner_chain_id: String,
+    pub size: u8,
+    pub weights: Vec<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -110,10 +447,37 @@ pub struct Board {
     pub ships: Vec<Ship>,
 }
 
+#[derive(
+    Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, async_graphql::Enum,
+)]
+pub enum Terrain {
+    #[default]
+    Water,
+    Island,
+    Mine,
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Cell {
     pub ship_id: Option<u8>,
     pub attacked: bool,
+    pub terrain: Terrain,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttackOutcome {
+    pub hit: bool,
+    pub sunk: bool,
+    pub ship_id: Option<u8>,
+    pub game_over: bool,
+    pub mine: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct WeaponOutcome {
+    pub shots: Vec<ShotResult>,
+    pub sonar_count: Option<u8>,
+    pub game_over: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -129,6 +493,7 @@ pub struct MyCellView {
     pub col: u8,
     pub ship_id: Option<u8>,
     pub attacked: bool,
+    pub terrain: Terrain,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, async_graphql::SimpleObject)]
@@ -138,6 +503,15 @@ pub struct ShipView {
     pub cells: Vec<Coord>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, async_graphql::SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+pub struct ShipStatus {
+    pub id: u8,
+    pub size: u8,
+    pub hits: u8,
+    pub sunk: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, async_graphql::SimpleObject)]
 #[graphql(rename_fields = "camelCase")]
 pub struct MyBoardView {
@@ -146,8 +520,14 @@ pub struct MyBoardView {
     pub ships: Vec<ShipView>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, async_graphql::InputObject)]
-#[graphql(rename_fields = "camelCase")]
+#[derive(
+    Debug, Clone, Serialize, Deserialize, async_graphql::SimpleObject, async_graphql::InputObject,
+)]
+#[graphql(
+    rename_fields = "camelCase",
+    name = "ShipPlacement",
+    input_name = "ShipPlacementInput"
+)]
 pub struct ShipPlacementInput {
     pub row: u8,
     pub col: u8,
@@ -155,39 +535,452 @@ pub struct ShipPlacementInput {
     pub axis: Axis,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, async_graphql::InputObject)]
+#[graphql(rename_fields = "camelCase")]
+pub struct ShapePlacementInput {
+    pub shape_id: String,
+    pub row: u8,
+    pub col: u8,
+    #[graphql(default)]
+    pub rotation: Rotation,
+    #[graphql(default)]
+    pub reflected: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Operation {
-    CreateRoom { host_name: String },
-    JoinRoom { host_chain_id: String, player_name: String },
+    CreateRoom {
+        host_name: String,
+        settings: RoomSettings,
+        join_code: Option<String>,
+    },
+    JoinRoom {
+        host_chain_id: String,
+        player_name: String,
+        join_code: Option<String>,
+        /// Locked with the host; must match the room's stake.
+        stake: Amount,
+    },
     SearchPlayer {
-        orchestrator_chain_id: String,
+        /// Defaults to the matchmaking shard for `region` and the game mode.
+        orchestrator_chain_id: Option<String>,
         player_name: String,
+        preferences: MatchPreferences,
+        region: Option<String>,
+    },
+    SubmitBoard {
+        ships: Vec<ShipPlacementInput>,
+        shapes: Vec<ShapePlacementInput>,
+        mines: Vec<Coord>,
     },
-    SubmitBoard { ships: Vec<ShipPlacementInput> },
     StartGame,
-    Attack { row: u8, col: u8 },
+    Attack {
+        row: u8,
+        col: u8,
+        target_chain_id: Option<String>,
+    },
+    Salvo {
+        shots: Vec<Coord>,
+        target_chain_id: Option<String>,
+    },
+    UseWeapon {
+        weapon: Weapon,
+        row: u8,
+        col: u8,
+        target_chain_id: Option<String>,
+    },
     LeaveRoom,
     RequestFriend { target_chain_id: String },
     AcceptFriend { requester_chain_id: String },
     DeclineFriend { requester_chain_id: String },
     InviteFriend { friend_chain_id: String },
-    AcceptInvite { host_chain_id: String, player_name: String },
+    AcceptInvite {
+        host_chain_id: String,
+        player_name: String,
+        stake: Amount,
+    },
     DeclineInvite { host_chain_id: String },
+    /// Turns this chain into a bot opponent, or back into a regular player with `None`.
+    SetBotMode {
+        bot_name: Option<String>,
+    },
+    SaveLayout {
+        name: String,
+        ships: Vec<ShipPlacementInput>,
+    },
+    DeleteLayout {
+        name: String,
+    },
+    SubmitSavedLayout {
+        name: String,
+    },
+    /// Lists the open room hosted here in the lobby of an orchestrator chain.
+    PublishRoom {
+        /// Defaults to the first official orchestrator.
+        orchestrator_chain_id: Option<String>,
+        room_name: String,
+    },
+    UnpublishRoom,
+    /// Eliminates the player the game has been waiting on for longer than `turn_seconds`. The claim is
+    /// decided on the claimant's chain and reported to every other player.
+    ClaimTimeout,
+    OfferDraw,
+    AcceptDraw,
+    ProposeAbort,
+    AcceptAbort,
+    /// Forwards the players who waited too long here to the next matchmaking shard.
+    RebalanceMatchmaking,
+    /// Opens registration for a tournament organized by this chain.
+    CreateTournament {
+        name: String,
+        format: TournamentFormat,
+        settings: RoomSettings,
+        max_players: u32,
+        /// Swiss rounds; defaults to enough rounds to leave one undefeated player.
+        rounds: Option<u32>,
+    },
+    StartTournament,
+    /// Settles a current-round match by hand, e.g. after a no-show or a room that ended without a winner.
+    DecideTournamentMatch {
+        host_chain_id: String,
+        winner_chain_id: String,
+    },
+    RegisterTournament {
+        organizer_chain_id: String,
+        player_name: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, async_graphql::SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+pub struct SavedLayout {
+    pub name: String,
+    pub ships: Vec<ShipPlacementInput>,
+}
+
+/// What a searching player wants from a match; only players with compatible preferences are paired.
+#[derive(
+    Debug,
+    Clone,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    async_graphql::SimpleObject,
+    async_graphql::InputObject,
+)]
+#[graphql(rename_fields = "camelCase", input_name = "MatchPreferencesInput")]
+pub struct MatchPreferences {
+    #[graphql(default = 10)]
+    pub board_size: u8,
+    #[graphql(default)]
+    pub game_mode: GameMode,
+    /// Required fleet; empty leaves the fleet free.
+    #[graphql(default)]
+    pub fleet: Vec<u8>,
+    /// `None` accepts any turn rule.
+    pub turn_rule: Option<TurnRule>,
+    #[graphql(default)]
+    pub ranked: bool,
+    /// Seconds per turn; `None` for no time control.
+    pub turn_seconds: Option<u32>,
+}
+
+impl Default for MatchPreferences {
+    fn default() -> Self {
+        MatchPreferences {
+            board_size: BOARD_SIZE,
+            game_mode: GameMode::default(),
+            fleet: Vec::new(),
+            turn_rule: None,
+            ranked: false,
+            turn_seconds: None,
+        }
+    }
+}
+
+/// A searching player's place in an orchestrator's queue, as last reported by the orchestrator.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, async_graphql::SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+pub struct QueueStatus {
+    pub orchestrator_chain_id: String,
+    /// 1-based; the player at position 1 has waited longest.
+    pub position: u32,
+    pub queue_size: u32,
+    /// Average wait of recent matches; `None` until the orchestrator has paired anyone.
+    pub estimated_wait_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, async_graphql::SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+pub struct ModeQueueCount {
+    pub game_mode: GameMode,
+    pub ranked: bool,
+    pub players: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, async_graphql::SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+pub struct MatchmakingQueueStats {
+    pub size: u32,
+    pub average_wait_secs: Option<u64>,
+    pub by_mode: Vec<ModeQueueCount>,
+}
+
+/// Lobby entry of an open room, kept by the orchestrator chain.
+#[derive(Debug, Clone, Serialize, Deserialize, async_graphql::SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+pub struct RoomListing {
+    pub room_id: String,
+    pub room_name: String,
+    pub host_chain_id: String,
+    pub host_name: String,
+    pub board_size: u8,
+    pub settings: RoomSettings,
+    pub private: bool,
+    pub players: u8,
+    pub listed_at: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, async_graphql::InputObject)]
+#[graphql(rename_fields = "camelCase")]
+pub struct LobbyFilter {
+    pub game_mode: Option<GameMode>,
+    pub max_players: Option<u8>,
+    pub teams: Option<bool>,
+    pub name_contains: Option<String>,
+    #[graphql(default)]
+    pub hide_private: bool,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, async_graphql::Enum,
+)]
+pub enum TournamentFormat {
+    #[default]
+    SingleElimination,
+    Swiss,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, async_graphql::Enum)]
+pub enum TournamentStatus {
+    Registration,
+    Running,
+    Finished,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, async_graphql::SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+pub struct TournamentPlayer {
+    pub chain_id: String,
+    pub name: String,
+    pub wins: u32,
+    pub losses: u32,
+    pub eliminated: bool,
+    pub had_bye: bool,
+    /// Chains already met, so Swiss rounds avoid rematches.
+    pub opponents: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, async_graphql::SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+pub struct TournamentMatch {
+    pub round: u32,
+    pub host_chain_id: String,
+    /// `None` for a bye, which the host wins straight away.
+    pub guest_chain_id: Option<String>,
+    pub winner_chain_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, async_graphql::SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+pub struct Tournament {
+    pub tournament_id: String,
+    pub name: String,
+    pub format: TournamentFormat,
+    /// Settings of every match; matches are always duels.
+    pub settings: RoomSettings,
+    pub max_players: u32,
+    /// Rounds of a Swiss tournament, fixed when it starts; single elimination runs until one player is left.
+    pub rounds: u32,
+    pub status: TournamentStatus,
+    pub round: u32,
+    pub players: Vec<TournamentPlayer>,
+    /// Every match so far, round by round; this is the bracket.
+    pub matches: Vec<TournamentMatch>,
+    pub champion_chain_id: Option<String>,
+}
+
+/// The tournament match hosted here, reported to the organizer once the room ends.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, async_graphql::SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+pub struct TournamentMatchRef {
+    pub organizer_chain_id: String,
+    pub tournament_id: String,
+    pub round: u32,
+    pub room_id: String,
+}
+
+/// Where the room hosted on this chain is listed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublishedRoom {
+    pub orchestrator_chain_id: String,
+    pub room_id: String,
+    pub room_name: String,
+}
+
+/// An orchestrator running one matchmaking queue, optionally for the players of a region.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatchmakingShard {
+    pub chain_id: ChainId,
+    #[serde(default)]
+    pub region: Option<String>,
+}
+
+/// Application parameters. Without official orchestrators, any chain may run matchmaking and a lobby.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BattleshipParameters {
+    #[serde(default)]
+    pub orchestrator_chain_ids: Vec<ChainId>,
+    #[serde(default)]
+    pub matchmaking_shards: Vec<MatchmakingShard>,
+    /// How long a player waits on a shard before moving on to the next one; 0 never moves players.
+    #[serde(default = "default_stale_wait_secs")]
+    pub stale_wait_secs: u64,
+}
+
+fn default_stale_wait_secs() -> u64 {
+    120
+}
+
+impl Default for BattleshipParameters {
+    fn default() -> Self {
+        Self {
+            orchestrator_chain_ids: Vec::new(),
+            matchmaking_shards: Vec::new(),
+            stale_wait_secs: default_stale_wait_secs(),
+        }
+    }
+}
+
+impl BattleshipParameters {
+    pub fn is_orchestrator(&self, chain_id: ChainId) -> bool {
+        (self.orchestrator_chain_ids.is_empty() && self.matchmaking_shards.is_empty())
+            || self.orchestrator_chain_ids.contains(&chain_id)
+            || self
+                .matchmaking_shards
+                .iter()
+                .any(|s| s.chain_id == chain_id)
+    }
+
+    /// The shard a player should search on: the first one serving their region, or else one picked by a hash of
+    /// the game mode among the shards without a region (all shards if every one has a region).
+    pub fn shard_for(&self, region: Option<&str>, game_mode: GameMode) -> Option<ChainId> {
+        if let Some(shard) = self
+            .matchmaking_shards
+            .iter()
+            .find(|s| region.is_some() && s.region.as_deref() == region)
+        {
+            return Some(shard.chain_id);
+        }
+        let mut shards: Vec<&MatchmakingShard> = self
+            .matchmaking_shards
+            .iter()
+            .filter(|s| s.region.is_none())
+            .collect();
+        if shards.is_empty() {
+            shards = self.matchmaking_shards.iter().collect();
+        }
+        if shards.is_empty() {
+            return None;
+        }
+        let index = fnv1a(format!("{:?}", game_mode).as_bytes()) % shards.len() as u64;
+        Some(shards[index as usize].chain_id)
+    }
+
+    /// The shard after `chain_id` in the configured order, wrapping around.
+    pub fn sibling_shard(&self, chain_id: ChainId) -> Option<ChainId> {
+        let shards = &self.matchmaking_shards;
+        let pos = shards.iter().position(|s| s.chain_id == chain_id)?;
+        if shards.len() < 2 {
+            return None;
+        }
+        Some(shards[(pos + 1) % shards.len()].chain_id)
+    }
+
+    /// The orchestrator a request goes to: the requested one, which must be official, or the first official one.
+    pub fn choose_orchestrator(&self, requested: Option<&str>) -> Result<ChainId, String> {
+        match requested {
+            Some(requested) => {
+                let chain_id: ChainId = requested
+                    .parse()
+                    .map_err(|_| "Invalid orchestrator chain ID".to_string())?;
+                if !self.is_orchestrator(chain_id) {
+                    return Err("Not an official orchestrator".into());
+                }
+                Ok(chain_id)
+            }
+            None => self
+                .orchestrator_chain_ids
+                .first()
+                .copied()
+                .or_else(|| self.matchmaking_shards.first().map(|s| s.chain_id))
+                .ok_or_else(|| "No official orchestrator; pass orchestratorChainId".to_string()),
+        }
+    }
+}
+
+/// Instantiation argument of a bot chain. Instantiating with `null` creates a regular player chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BotConfig {
+    pub name: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MatchmakingPlayer {
     pub chain_id: String,
     pub player_name: String,
+    pub preferences: MatchPreferences,
+    pub enqueued_at: u64,
+    /// How many times the player was forwarded to a sibling shard.
+    pub hops: u8,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CrossChainMessage {
-    JoinRequest { player_chain_id: ChainId, player_name: String },
-    InitialStateSync { room: Room },
-    RoomSync { room: Room },
-    BoardSubmittedNotice { player_chain_id: ChainId },
-    AttackRequest { attacker_chain_id: ChainId, row: u8, col: u8 },
+    JoinRequest {
+        player_chain_id: ChainId,
+        player_name: String,
+        join_code: Option<String>,
+        stake: Amount,
+    },
+    JoinRejected {
+        host_chain_id: ChainId,
+        reason: String,
+    },
+    InitialStateSync {
+        room: Room,
+    },
+    RoomSync {
+        room: Room,
+    },
+    BoardSubmittedNotice {
+        player_chain_id: ChainId,
+        ship_count: u8,
+    },
+    AttackRequest {
+        attacker_chain_id: ChainId,
+        row: u8,
+        col: u8,
+    },
+    SalvoAttackRequest {
+        attacker_chain_id: ChainId,
+        shots: Vec<Coord>,
+    },
+    WeaponAttackRequest {
+        attacker_chain_id: ChainId,
+        shot: WeaponShot,
+    },
     RevealResult {
         defender_chain_id: ChainId,
         row: u8,
@@ -198,65 +991,320 @@ pub enum CrossChainMessage {
         sunk: bool,
         sunk_ship_cells: Option<Vec<Coord>>,
         adjacent_coords: Option<Vec<Coord>>,
+        mine_hit: bool,
+        next_attacker: ChainId,
+        game_over: bool,
+        winner_chain_id: Option<ChainId>,
+    },
+    SalvoRevealResult {
+        defender_chain_id: ChainId,
+        shots: Vec<ShotResult>,
+        valid: bool,
+        error: Option<String>,
+        next_attacker: ChainId,
+        game_over: bool,
+        winner_chain_id: Option<ChainId>,
+    },
+    WeaponRevealResult {
+        defender_chain_id: ChainId,
+        shot: WeaponShot,
+        valid: bool,
+        error: Option<String>,
+        shots: Vec<ShotResult>,
+        sonar_count: Option<u8>,
         next_attacker: ChainId,
         game_over: bool,
         winner_chain_id: Option<ChainId>,
     },
-    LeaveNotice { player_chain_id: ChainId },
-    FriendRequest { requester_chain_id: ChainId },
-    FriendAccepted { target_chain_id: ChainId },
-    RoomInvitation { host_chain_id: ChainId, timestamp: String },
-    RoomInvitationCancelled { host_chain_id: ChainId },
+    MineBlast {
+        player_chain_id: ChainId,
+        shot: ShotResult,
+        fleet_destroyed: bool,
+    },
+    /// Room state after a resolved attack, sent by the defender to the players not involved in it.
+    /// The attacker's teammates also record `shots` on their view of the defender's board.
+    TurnSync {
+        defender_chain_id: ChainId,
+        attacker_chain_id: ChainId,
+        shots: Vec<ShotResult>,
+        room: Room,
+    },
+    TeammateBoard {
+        player_chain_id: ChainId,
+        board: Board,
+    },
+    LeaveNotice {
+        player_chain_id: ChainId,
+    },
+    TimeoutClaim {
+        claimant_chain_id: ChainId,
+        stalled_chain_id: ChainId,
+    },
+    FriendRequest {
+        requester_chain_id: ChainId,
+    },
+    FriendAccepted {
+        target_chain_id: ChainId,
+    },
+    RoomInvitation {
+        host_chain_id: ChainId,
+        timestamp: String,
+    },
+    RoomInvitationCancelled {
+        host_chain_id: ChainId,
+    },
     MatchmakingEnqueue {
         player_chain_id: ChainId,
         player_name: String,
+        preferences: MatchPreferences,
+        /// Non-zero when a shard forwards a waiting player.
+        hops: u8,
     },
     MatchmakingEnqueued {
         orchestrator_chain_id: ChainId,
+        position: u32,
+        queue_size: u32,
+        estimated_wait_secs: Option<u64>,
     },
     MatchmakingStart {
         host_name: String,
         guest_chain_id: ChainId,
         guest_name: String,
+        settings: RoomSettings,
     },
     MatchmakingFound {
         host_chain_id: ChainId,
     },
-}
-
-pub fn empty_enemy_view(size: u8) -> EnemyBoardView {
-    EnemyBoardView {
-        size,
-        cells: vec![EnemyCell::Unknown; (size as usize) * (size as usize)],
-    }
-}
-
-fn idx(size: u8, row: u8, col: u8) -> usize {
-    (row as usize) * (size as usize) + (col as usize)
-}
-
+    /// The host of a match turned out to be busy; the guest has to search again.
+    MatchmakingCancelled {
+        host_chain_id: ChainId,
+    },
+    ListRoom {
+        listing: RoomListing,
+    },
+    UnlistRoom {
+        host_chain_id: ChainId,
+    },
+    TournamentRegister {
+        player_chain_id: ChainId,
+        player_name: String,
+    },
+    TournamentRegistered {
+        organizer_chain_id: ChainId,
+        tournament_id: String,
+        error: Option<String>,
+    },
+    /// Tells the guest of a tournament match which host will open the room.
+    TournamentMatchFound {
+        host_chain_id: ChainId,
+    },
+    TournamentMatchStart {
+        tournament_id: String,
+        round: u32,
+        host_name: String,
+        guest_chain_id: ChainId,
+        guest_name: String,
+        settings: RoomSettings,
+    },
+    DrawOffered {
+        player_chain_id: ChainId,
+    },
+    DrawAccepted {
+        player_chain_id: ChainId,
+    },
+    AbortProposed {
+        player_chain_id: ChainId,
+    },
+    AbortAccepted {
+        player_chain_id: ChainId,
+    },
+    TournamentResult {
+        tournament_id: String,
+        round: u32,
+        host_chain_id: ChainId,
+        winner_chain_id: ChainId,
+    },
+}
+
+pub fn empty_enemy_view(owner_chain_id: &str, size: u8, islands: &[Coord]) -> EnemyBoardView {
+    let mut view = EnemyBoardView {
+        owner_chain_id: owner_chain_id.to_string(),
+        size,
+        cells: vec![EnemyCell::Unknown; (size as usize) * (size as usize)],
+        sunk_ships: Vec::new(),
+    };
+    for island in islands {
+        set_enemy_view_cell(&mut view, island.row, island.col, EnemyCell::Island).ok();
+    }
+    view
+}
+
+fn idx(size: u8, row: u8, col: u8) -> usize {
+    (row as usize) * (size as usize) + (col as usize)
+}
+
+const MAX_SHAPE_CELLS: usize = 8;
+
+pub fn validate_room_settings(size: u8, settings: &RoomSettings) -> Result<(), String> {
+    validate_custom_shapes(&settings.custom_shapes)?;
+    if !(2..=4).contains(&settings.max_players) {
+        return Err("Rooms seat between 2 and 4 players".into());
+    }
+    if settings.teams && settings.max_players != 4 {
+        return Err("Team games need 4 seats".into());
+    }
+    if settings.salvo_shots == Some(0) {
+        return Err("Salvo must allow at least one shot".into());
+    }
+    let max_index = size.saturating_sub(1);
+    for (i, island) in settings.islands.iter().enumerate() {
+        if island.row > max_index || island.col > max_index {
+            return Err("Island out of bounds".into());
+        }
+        if settings.islands[..i].contains(island) {
+            return Err("Duplicate island".into());
+        }
+    }
+    if settings.islands.len() > (size as usize) * (size as usize) / 4 {
+        return Err("Too many islands".into());
+    }
+    if settings.fleet.iter().any(|&ship| ship == 0 || ship > size) {
+        return Err("Fleet ship sizes must be between 1 and the board size".into());
+    }
+    if !matches!(settings.best_of, 1 | 3 | 5 | 7) {
+        return Err("Series are best of 1, 3, 5 or 7".into());
+    }
+    Ok(())
+}
+
+pub fn builtin_shapes() -> Vec<ShapeDefinition> {
+    let shape = |shape_id: &str, cells: &[(u8, u8)]| ShapeDefinition {
+        shape_id: shape_id.to_string(),
+        cells: cells.iter().map(|&(row, col)| Coord { row, col }).collect(),
+    };
+    let mut shapes: Vec<ShapeDefinition> = (1..=5u8)
+        .map(|length| ShapeDefinition {
+            shape_id: format!("I{}", length),
+            cells: (0..length).map(|col| Coord { row: 0, col }).collect(),
+        })
+        .collect();
+    shapes.push(shape("L", &[(0, 0), (1, 0), (2, 0), (2, 1)]));
+    shapes.push(shape("T", &[(0, 0), (0, 1), (0, 2), (1, 1)]));
+    shapes.push(shape("O", &[(0, 0), (0, 1), (1, 0), (1, 1)]));
+    shapes
+}
+
+pub fn shape_catalogue(custom_shapes: &[ShapeDefinition]) -> Vec<ShapeDefinition> {
+    let mut shapes = builtin_shapes();
+    shapes.extend(custom_shapes.iter().cloned());
+    shapes
+}
+
+pub fn validate_custom_shapes(custom_shapes: &[ShapeDefinition]) -> Result<(), String> {
+    let builtin = builtin_shapes();
+    for (i, shape) in custom_shapes.iter().enumerate() {
+        if shape.shape_id.is_empty() {
+            return Err("Shape id must not be empty".into());
+        }
+        if builtin
+            .iter()
+            .chain(&custom_shapes[..i])
+            .any(|s| s.shape_id == shape.shape_id)
+        {
+            return Err(format!("Duplicate shape id '{}'", shape.shape_id));
+        }
+        if shape.cells.is_empty() || shape.cells.len() > MAX_SHAPE_CELLS {
+            return Err(format!(
+                "Shape '{}' must have between 1 and {} cells",
+                shape.shape_id, MAX_SHAPE_CELLS
+            ));
+        }
+        for (j, cell) in shape.cells.iter().enumerate() {
+            if shape.cells[..j].contains(cell) {
+                return Err(format!("Shape '{}' repeats a cell", shape.shape_id));
+            }
+        }
+        let mut reached = vec![false; shape.cells.len()];
+        reached[0] = true;
+        let mut stack = vec![0usize];
+        while let Some(current) = stack.pop() {
+            let from = shape.cells[current];
+            for (k, cell) in shape.cells.iter().enumerate() {
+                if !reached[k] && from.row.abs_diff(cell.row) + from.col.abs_diff(cell.col) == 1 {
+                    reached[k] = true;
+                    stack.push(k);
+                }
+            }
+        }
+        if reached.iter().any(|r| !r) {
+            return Err(format!(
+                "Shape '{}' cells must be edge-connected",
+                shape.shape_id
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Applies reflection (mirroring columns) then clockwise rotation to the shape cells and
+/// shifts the result so its top-left bounding corner is at (0, 0).
+pub fn orient_shape(cells: &[Coord], rotation: Rotation, reflected: bool) -> Vec<(i16, i16)> {
+    let mut offsets: Vec<(i16, i16)> = cells
+        .iter()
+        .map(|cell| {
+            let (mut r, mut c) = (cell.row as i16, cell.col as i16);
+            if reflected {
+                c = -c;
+            }
+            for _ in 0..rotation.quarter_turns() {
+                (r, c) = (c, -r);
+            }
+            (r, c)
+        })
+        .collect();
+    let min_r = offsets.iter().map(|o| o.0).min().unwrap_or(0);
+    let min_c = offsets.iter().map(|o| o.1).min().unwrap_or(0);
+    for offset in &mut offsets {
+        offset.0 -= min_r;
+        offset.1 -= min_c;
+    }
+    offsets.sort();
+    offsets
+}
+
 pub fn validate_and_build_board(
     size: u8,
     placements: &[ShipPlacementInput],
+    shaped: &[ShapePlacementInput],
+    mines: &[Coord],
+    settings: &RoomSettings,
 ) -> Result<Board, String> {
     if size == 0 {
         return Err("Invalid board size".into());
     }
+    let placement_rule = settings.placement_rule;
     let mut cells = vec![
         Cell {
             ship_id: None,
-            attacked: false
+            attacked: false,
+            terrain: Terrain::Water,
         };
         (size as usize) * (size as usize)
     ];
+    for island in &settings.islands {
+        if island.row < size && island.col < size {
+            cells[idx(size, island.row, island.col)].terrain = Terrain::Island;
+        }
+    }
     let mut ships: Vec<Ship> = Vec::new();
     let mut next_ship_id: u8 = 0;
+    let max_index = size.saturating_sub(1);
+    let mut footprints: Vec<Vec<Coord>> = Vec::with_capacity(placements.len() + shaped.len());
 
     for placement in placements {
         if placement.length == 0 {
             return Err("Ship length must be > 0".into());
         }
-        let max_index = size.saturating_sub(1);
         if placement.row > max_index || placement.col > max_index {
             return Err("Ship start out of bounds".into());
         }
@@ -272,31 +1320,61 @@ pub fn validate_and_build_board(
             }
             ship_cells.push(Coord { row: r, col: c });
         }
+        footprints.push(ship_cells);
+    }
+
+    if !shaped.is_empty() {
+        let catalogue = shape_catalogue(&settings.custom_shapes);
+        for placement in shaped {
+            let shape = catalogue
+                .iter()
+                .find(|s| s.shape_id == placement.shape_id)
+                .ok_or_else(|| format!("Unknown shape '{}'", placement.shape_id))?;
+            if placement.row > max_index || placement.col > max_index {
+                return Err("Ship start out of bounds".into());
+            }
+            let mut ship_cells: Vec<Coord> = Vec::with_capacity(shape.cells.len());
+            for (dr, dc) in orient_shape(&shape.cells, placement.rotation, placement.reflected) {
+                let r = placement.row as i16 + dr;
+                let c = placement.col as i16 + dc;
+                if r > max_index as i16 || c > max_index as i16 {
+                    return Err("Ship out of bounds".into());
+                }
+                ship_cells.push(Coord {
+                    row: r as u8,
+                    col: c as u8,
+                });
+            }
+            footprints.push(ship_cells);
+        }
+    }
 
+    for ship_cells in footprints {
         for coord in &ship_cells {
             let index = idx(size, coord.row, coord.col);
             if cells[index].ship_id.is_some() {
                 return Err("Ships overlap".into());
             }
-            for dr in [-1i8, 0, 1] {
-                for dc in [-1i8, 0, 1] {
-                    if dr == 0 && dc == 0 {
-                        continue;
-                    }
-                    let nr = coord.row as i16 + dr as i16;
-                    let nc = coord.col as i16 + dc as i16;
-                    if nr < 0 || nc < 0 {
-                        continue;
-                    }
-                    let nr = nr as u8;
-                    let nc = nc as u8;
-                    if nr > max_index || nc > max_index {
-                        continue;
-                    }
-                    let nindex = idx(size, nr, nc);
-                    if cells[nindex].ship_id.is_some() {
-                        return Err("Ships must not touch (including diagonals)".into());
-                    }
+            if cells[index].terrain == Terrain::Island {
+                return Err("Ships cannot be placed on islands".into());
+            }
+            for &(dr, dc) in placement_rule.blocked_neighbours() {
+                let nr = coord.row as i16 + dr as i16;
+                let nc = coord.col as i16 + dc as i16;
+                if nr < 0 || nc < 0 {
+                    continue;
+                }
+                let nr = nr as u8;
+                let nc = nc as u8;
+                if nr > max_index || nc > max_index {
+                    continue;
+                }
+                let nindex = idx(size, nr, nc);
+                if cells[nindex].ship_id.is_some() {
+                    return Err(match placement_rule {
+                        PlacementRule::DiagonalTouch => "Ships must not share an edge".into(),
+                        _ => "Ships must not touch (including diagonals)".into(),
+                    });
                 }
             }
         }
@@ -313,15 +1391,64 @@ pub fn validate_and_build_board(
         next_ship_id = next_ship_id.saturating_add(1);
     }
 
+    if !settings.fleet.is_empty() {
+        let mut sizes: Vec<u8> = ships.iter().map(|s| s.cells.len() as u8).collect();
+        let mut fleet = settings.fleet.clone();
+        sizes.sort_unstable();
+        fleet.sort_unstable();
+        if sizes != fleet {
+            return Err(format!(
+                "Fleet must consist of ships of sizes {:?}",
+                settings.fleet
+            ));
+        }
+    }
+
+    if mines.len() > settings.mines_per_player as usize {
+        return Err(format!(
+            "At most {} mines allowed",
+            settings.mines_per_player
+        ));
+    }
+    for mine in mines {
+        if mine.row > max_index || mine.col > max_index {
+            return Err("Mine out of bounds".into());
+        }
+        let index = idx(size, mine.row, mine.col);
+        match cells[index].terrain {
+            Terrain::Island => return Err("Mines cannot be placed on islands".into()),
+            Terrain::Mine => return Err("Duplicate mine".into()),
+            Terrain::Water => {}
+        }
+        if cells[index].ship_id.is_some() {
+            return Err("Mines cannot be placed on ships".into());
+        }
+        // Keep mines out of the ring that sunk padding reveals as water.
+        for &(dr, dc) in placement_rule.blocked_neighbours() {
+            let nr = mine.row as i16 + dr as i16;
+            let nc = mine.col as i16 + dc as i16;
+            if nr < 0 || nc < 0 || nr > max_index as i16 || nc > max_index as i16 {
+                continue;
+            }
+            if cells[idx(size, nr as u8, nc as u8)].ship_id.is_some() {
+                return Err("Mines must not touch ships".into());
+            }
+        }
+        cells[index].terrain = Terrain::Mine;
+    }
+
     Ok(Board { size, cells, ships })
 }
 
-pub fn apply_attack(board: &mut Board, row: u8, col: u8) -> Result<(bool, bool, Option<u8>, bool), String> {
+pub fn apply_attack(board: &mut Board, row: u8, col: u8) -> Result<AttackOutcome, String> {
     let max_index = board.size.saturating_sub(1);
     if row > max_index || col > max_index {
         return Err("Attack out of bounds".into());
     }
     let index = idx(board.size, row, col);
+    if board.cells[index].terrain == Terrain::Island {
+        return Err("Cannot attack an island".into());
+    }
     if board.cells[index].attacked {
         return Err("Cell already attacked".into());
     }
@@ -338,12 +1465,19 @@ pub fn apply_attack(board: &mut Board, row: u8, col: u8) -> Result<(bool, bool,
         .ships
         .iter()
         .all(|ship| ship.cells.iter().all(|c| board.cells[idx(board.size, c.row, c.col)].attacked));
-    Ok((hit, sunk, ship_id, game_over))
+    Ok(AttackOutcome {
+        hit,
+        sunk,
+        ship_id,
+        game_over,
+        mine: board.cells[index].terrain == Terrain::Mine,
+    })
 }
 
 pub fn apply_sunk_padding(
     board: &mut Board,
     ship_id: u8,
+    placement_rule: PlacementRule,
 ) -> Result<(Vec<Coord>, Vec<Coord>), String> {
     let ship = board
         .ships
@@ -354,41 +1488,2887 @@ pub fn apply_sunk_padding(
     let max_index = board.size.saturating_sub(1);
 
     for coord in &ship.cells {
-        for dr in [-1i8, 0, 1] {
-            for dc in [-1i8, 0, 1] {
-                let nr = coord.row as i16 + dr as i16;
-                let nc = coord.col as i16 + dc as i16;
-                if nr < 0 || nc < 0 {
-                    continue;
-                }
-                let nr = nr as u8;
-                let nc = nc as u8;
-                if nr > max_index || nc > max_index {
-                    continue;
+        for &(dr, dc) in placement_rule.blocked_neighbours() {
+            let nr = coord.row as i16 + dr as i16;
+            let nc = coord.col as i16 + dc as i16;
+            if nr < 0 || nc < 0 {
+                continue;
+            }
+            let nr = nr as u8;
+            let nc = nc as u8;
+            if nr > max_index || nc > max_index {
+                continue;
+            }
+            let index = idx(board.size, nr, nc);
+            let cell = &mut board.cells[index];
+            if cell.ship_id.is_some() || cell.terrain == Terrain::Island {
+                continue;
+            }
+            if cell.attacked {
+                continue;
+            }
+            cell.attacked = true;
+            adjacent.push(Coord { row: nr, col: nc });
+        }
+    }
+
+    Ok((ship.cells.clone(), adjacent))
+}
+
+pub fn apply_salvo(
+    board: &mut Board,
+    shots: &[Coord],
+    placement_rule: PlacementRule,
+) -> Result<(Vec<ShotResult>, bool), String> {
+    if shots.is_empty() {
+        return Err("Salvo must contain at least one shot".into());
+    }
+    let max_index = board.size.saturating_sub(1);
+    for (i, shot) in shots.iter().enumerate() {
+        if shot.row > max_index || shot.col > max_index {
+            return Err("Attack out of bounds".into());
+        }
+        let cell = board.cells[idx(board.size, shot.row, shot.col)];
+        if cell.terrain == Terrain::Island {
+            return Err("Cannot attack an island".into());
+        }
+        if cell.attacked {
+            return Err("Cell already attacked".into());
+        }
+        if shots[..i].contains(shot) {
+            return Err("Duplicate shot in salvo".into());
+        }
+    }
+
+    let mut results = Vec::with_capacity(shots.len());
+    let mut game_over = false;
+    for shot in shots {
+        if board.cells[idx(board.size, shot.row, shot.col)].attacked {
+            // Already revealed as padding around a ship sunk earlier in this salvo.
+            results.push(ShotResult {
+                row: shot.row,
+                col: shot.col,
+                hit: false,
+                sunk: false,
+                mine: false,
+                sunk_ship_cells: None,
+                adjacent_coords: None,
+            });
+            continue;
+        }
+        let (result, over) = fire_shot(board, *shot, placement_rule)?;
+        game_over |= over;
+        results.push(result);
+    }
+    Ok((results, game_over))
+}
+
+fn fire_shot(
+    board: &mut Board,
+    shot: Coord,
+    placement_rule: PlacementRule,
+) -> Result<(ShotResult, bool), String> {
+    let outcome = apply_attack(board, shot.row, shot.col)?;
+    let mut sunk_ship_cells = None;
+    let mut adjacent_coords = None;
+    if outcome.sunk {
+        if let Some(ship_id) = outcome.ship_id {
+            let (ship_cells, adjacent) = apply_sunk_padding(board, ship_id, placement_rule)?;
+            sunk_ship_cells = Some(ship_cells);
+            adjacent_coords = Some(adjacent);
+        }
+    }
+    let result = ShotResult {
+        row: shot.row,
+        col: shot.col,
+        hit: outcome.hit,
+        sunk: outcome.sunk,
+        mine: outcome.mine,
+        sunk_ship_cells,
+        adjacent_coords,
+    };
+    Ok((result, outcome.game_over))
+}
+
+/// Resolves a special weapon on the defender's board. Every attacked cell goes through
+/// `apply_attack`, so weapons follow the same island, mine and padding rules as plain shots.
+pub fn apply_weapon(
+    board: &mut Board,
+    shot: WeaponShot,
+    placement_rule: PlacementRule,
+) -> Result<WeaponOutcome, String> {
+    let max_index = board.size.saturating_sub(1);
+    if shot.row > max_index || shot.col > max_index {
+        return Err("Weapon target out of bounds".into());
+    }
+    match shot.weapon {
+        Weapon::Sonar => {
+            let mut count = 0;
+            for row in shot.row.saturating_sub(1)..=(shot.row + 1).min(max_index) {
+                for col in shot.col.saturating_sub(1)..=(shot.col + 1).min(max_index) {
+                    if board.cells[idx(board.size, row, col)].ship_id.is_some() {
+                        count += 1;
+                    }
                 }
-                let index = idx(board.size, nr, nc);
-                let cell = &mut board.cells[index];
-                if cell.ship_id.is_some() {
-                    continue;
+            }
+            Ok(WeaponOutcome {
+                shots: Vec::new(),
+                sonar_count: Some(count),
+                game_over: false,
+            })
+        }
+        Weapon::CrossBomb => {
+            let targets: Vec<Coord> = std::iter::once((0, 0))
+                .chain(EDGE_NEIGHBOURS)
+                .filter_map(|(dr, dc)| {
+                    let row = shot.row as i16 + dr as i16;
+                    let col = shot.col as i16 + dc as i16;
+                    if row < 0 || col < 0 || row > max_index as i16 || col > max_index as i16 {
+                        return None;
+                    }
+                    let cell = board.cells[idx(board.size, row as u8, col as u8)];
+                    if cell.attacked || cell.terrain == Terrain::Island {
+                        return None;
+                    }
+                    Some(Coord {
+                        row: row as u8,
+                        col: col as u8,
+                    })
+                })
+                .collect();
+            if targets.is_empty() {
+                return Err("Cross bomb has no cells left to hit".into());
+            }
+            let (shots, game_over) = apply_salvo(board, &targets, placement_rule)?;
+            Ok(WeaponOutcome {
+                shots,
+                sonar_count: None,
+                game_over,
+            })
+        }
+        Weapon::Torpedo => {
+            let mut shots = Vec::new();
+            let mut game_over = false;
+            for col in shot.col..=max_index {
+                let cell = board.cells[idx(board.size, shot.row, col)];
+                if cell.terrain == Terrain::Island {
+                    break;
                 }
                 if cell.attacked {
                     continue;
                 }
-                cell.attacked = true;
-                adjacent.push(Coord { row: nr, col: nc });
+                let (result, over) =
+                    fire_shot(board, Coord { row: shot.row, col }, placement_rule)?;
+                let stopped = result.hit || result.mine;
+                game_over |= over;
+                shots.push(result);
+                if stopped {
+                    break;
+                }
             }
+            if shots.is_empty() {
+                return Err("Torpedo has no cells left to hit".into());
+            }
+            Ok(WeaponOutcome {
+                shots,
+                sonar_count: None,
+                game_over,
+            })
         }
     }
+}
 
-    Ok((ship.cells.clone(), adjacent))
+pub fn attacker_keeps_turn(rule: TurnRule, hit: bool, sunk: bool) -> bool {
+    match rule {
+        TurnRule::ExtraShotOnHit => hit,
+        TurnRule::StrictAlternate => false,
+        TurnRule::ExtraShotOnSink => sunk,
+    }
 }
 
-pub fn set_enemy_view_cell(view: &mut EnemyBoardView, row: u8, col: u8, value: EnemyCell) -> Result<(), String> {
-    let max_index = view.size.saturating_sub(1);
-    if row > max_index || col > max_index {
-        return Err("Coord out of bounds".into());
+/// Weapons follow the room's turn rule in classic rooms and always pass the turn in salvo rooms.
+pub fn weapon_keeps_turn(settings: &RoomSettings, shots: &[ShotResult]) -> bool {
+    settings.game_mode == GameMode::Classic
+        && attacker_keeps_turn(
+            settings.turn_rule,
+            shots.iter().any(|s| s.hit),
+            shots.iter().any(|s| s.sunk),
+        )
+}
+
+/// Decides who fires next once a turn is resolved. The attacker keeps the turn when the turn rule
+/// allows it and they did not hit a mine; otherwise the turn moves on in seat order, skipping
+/// eliminated players and spending pending mine penalties (`MineEffect::SkipTurn`).
+/// Every chain calls this with the same inputs to keep `skip_next_turn` in sync.
+pub fn advance_turn(room: &mut Room, attacker: &str, keeps_turn: bool, mine_hit: bool) -> String {
+    if mine_hit
+        && room.settings.mine_effect == MineEffect::SkipTurn
+        && !room.skip_next_turn.iter().any(|c| c == attacker)
+    {
+        room.skip_next_turn.push(attacker.to_string());
     }
-    let index = idx(view.size, row, col);
-    view.cells[index] = value;
+    let attacker_alive = room
+        .players
+        .iter()
+        .any(|p| p.chain_id == attacker && !p.eliminated);
+    if keeps_turn && !mine_hit && attacker_alive {
+        return attacker.to_string();
+    }
+    next_in_turn_order(room, attacker)
+}
+
+fn next_in_turn_order(room: &mut Room, from: &str) -> String {
+    let mut current = from.to_string();
+    // Two laps, so that every pending penalty can be spent before the turn comes round again.
+    for _ in 0..2 * room.players.len() {
+        let next = next_seat(room, &current);
+        match room.skip_next_turn.iter().position(|c| *c == next) {
+            Some(pos) => {
+                room.skip_next_turn.remove(pos);
+                current = next;
+            }
+            None => return next,
+        }
+    }
+    current
+}
+
+/// Next player still in the game in seat order. In team rooms the turn goes to the other team
+/// whenever it has a player left.
+fn next_seat(room: &Room, from: &str) -> String {
+    let seats = room.players.len();
+    let start = room
+        .players
+        .iter()
+        .position(|p| p.chain_id == from)
+        .unwrap_or(0);
+    let team = room.players.get(start).and_then(|p| p.team);
+    let mut alive = (1..=seats)
+        .map(|step| &room.players[(start + step) % seats])
+        .filter(|p| !p.eliminated);
+    let first = alive.clone().next();
+    alive
+        .find(|p| team.is_none() || p.team != team)
+        .or(first)
+        .map(|p| p.chain_id.clone())
+        .unwrap_or_else(|| from.to_string())
+}
+
+fn same_side(a: &PlayerInfo, b: &PlayerInfo) -> bool {
+    a.chain_id == b.chain_id || (a.team.is_some() && a.team == b.team)
+}
+
+pub fn teammate_of(room: &Room, chain_id: &str) -> Option<String> {
+    let player = room.players.iter().find(|p| p.chain_id == chain_id)?;
+    room.players
+        .iter()
+        .find(|p| p.chain_id != chain_id && same_side(p, player))
+        .map(|p| p.chain_id.clone())
+}
+
+/// Seats alternate between the teams, so the turn order rotates across them.
+pub fn assign_teams(room: &mut Room) {
+    let teams = room.settings.teams;
+    for (seat, player) in room.players.iter_mut().enumerate() {
+        player.team = if teams { Some((seat % 2) as u8) } else { None };
+    }
+}
+
+/// Picks the opponent an attack goes to. The target may be omitted while only one opponent is left.
+pub fn resolve_target(room: &Room, attacker: &str, target: Option<&str>) -> Result<String, String> {
+    let attacker_info = room
+        .players
+        .iter()
+        .find(|p| p.chain_id == attacker)
+        .ok_or_else(|| "Attacker is not in the room".to_string())?;
+    let mut opponents = room
+        .players
+        .iter()
+        .filter(|p| !same_side(p, attacker_info) && !p.eliminated)
+        .map(|p| p.chain_id.clone());
+    match target {
+        Some(target) => opponents
+            .find(|c| c == target)
+            .ok_or_else(|| "Target is not an opponent still in the game".to_string()),
+        None => match (opponents.next(), opponents.next()) {
+            (Some(only), None) => Ok(only),
+            (None, _) => Err("No opponent left".into()),
+            _ => Err("Choose a target chain".into()),
+        },
+    }
+}
+
+pub fn eliminate_player(room: &mut Room, chain_id: &str) {
+    if let Some(p) = room.players.iter_mut().find(|p| p.chain_id == chain_id) {
+        p.ships_afloat = 0;
+        p.eliminated = true;
+    }
+    room.skip_next_turn.retain(|c| c != chain_id);
+}
+
+/// Takes a leaving player out of the room. Before the game starts they give up their seat;
+/// during the game their fleet counts as destroyed and the turn moves on if it was theirs.
+/// A series drops them for good, so they are not dealt back in for the next game.
+pub fn remove_player(room: &mut Room, chain_id: &str) {
+    let started = room.game_state == GameState::InGame;
+    if started {
+        eliminate_player(room, chain_id);
+        if room.current_attacker.as_deref() == Some(chain_id) {
+            room.current_attacker = Some(next_in_turn_order(room, chain_id));
+        }
+        if room.settings.best_of <= 1 {
+            return;
+        }
+    }
+    room.players.retain(|p| p.chain_id != chain_id);
+    // Teams stay as they are once a series is under way.
+    if !started && room.game_number == 1 {
+        assign_teams(room);
+    }
+}
+
+/// Whether every player left in the room is on the same side.
+fn one_side_left(room: &Room) -> bool {
+    let Some(first) = room.players.first() else {
+        return true;
+    };
+    room.players.iter().all(|p| same_side(p, first))
+}
+
+/// Who wins when `leaver` walks out of the whole room: the last opponent or opposing side still in the
+/// game. With several sides left there is no winner.
+pub fn forfeit_winner(room: &Room, leaver: &str) -> Option<String> {
+    let leaver = room.players.iter().find(|p| p.chain_id == leaver)?;
+    let mut remaining = room
+        .players
+        .iter()
+        .filter(|p| !p.eliminated && p.chain_id != leaver.chain_id);
+    let first = remaining.next()?;
+    if same_side(first, leaver) || remaining.any(|p| !same_side(p, first)) {
+        return None;
+    }
+    Some(first.chain_id.clone())
+}
+
+/// Why the room hosted on `host_chain_id` cannot seat a player who locked `stake`, if it cannot.
+pub fn join_rejection(room: Option<&Room>, host_chain_id: &str, stake: Amount) -> Option<String> {
+    let Some(room) = room else {
+        return Some("Room not found".into());
+    };
+    if room.host_chain_id != host_chain_id {
+        return Some("Only host can accept joins".into());
+    }
+    if room.status != RoomStatus::Active {
+        return Some("Room not active".into());
+    }
+    if room.game_state == GameState::InGame || room.game_state == GameState::Ended {
+        return Some("Game already started".into());
+    }
+    if room.players.len() >= room.settings.max_players as usize {
+        return Some("Room full".into());
+    }
+    if stake != room.settings.stake {
+        return Some(format!("Room stake is {}", room.settings.stake));
+    }
+    if stake_pot(&room.stakes)
+        .and_then(|pot| pot.try_add(stake).map_err(|e| e.to_string()))
+        .is_err()
+    {
+        return Some("Stake pot overflows".into());
+    }
+    None
+}
+
+/// Whether the first game of the room has started. Leaving before that is not a forfeit.
+pub fn room_started(room: &Room) -> bool {
+    room.game_number > 1 || matches!(room.game_state, GameState::InGame | GameState::Ended)
+}
+
+/// Removes and returns the stake of a player, e.g. to refund someone leaving before the game starts.
+pub fn take_stake(room: &mut Room, chain_id: &str) -> Option<Amount> {
+    let pos = room.stakes.iter().position(|s| s.chain_id == chain_id)?;
+    Some(room.stakes.remove(pos).amount)
+}
+
+/// Sum of the stakes escrowed for a room, or an error if it does not fit in an `Amount`.
+pub fn stake_pot(stakes: &[Stake]) -> Result<Amount, String> {
+    stakes
+        .iter()
+        .try_fold(Amount::ZERO, |pot, s| pot.try_add(s.amount))
+        .map_err(|_| "Stake pot overflows".to_string())
+}
+
+/// Who receives the escrowed stakes of an ended room: the winner's side splits the pot (the winner
+/// gets any remainder), and a room without a winner refunds every stake.
+pub fn stake_payouts(room: &Room) -> Result<Vec<(String, Amount)>, String> {
+    let winner = room
+        .players
+        .iter()
+        .find(|p| Some(&p.chain_id) == room.winner_chain_id.as_ref());
+    let Some(winner) = winner else {
+        return Ok(room
+            .stakes
+            .iter()
+            .map(|s| (s.chain_id.clone(), s.amount))
+            .collect());
+    };
+    let pot = stake_pot(&room.stakes)?;
+    let side: Vec<&PlayerInfo> = room
+        .players
+        .iter()
+        .filter(|p| same_side(p, winner))
+        .collect();
+    let share = pot.saturating_div(side.len() as u128);
+    let mut rest = pot;
+    let mut payouts = Vec::new();
+    for player in side.iter().filter(|p| p.chain_id != winner.chain_id) {
+        rest = rest.try_sub(share).unwrap_or(Amount::ZERO);
+        payouts.push((player.chain_id.clone(), share));
+    }
+    payouts.insert(0, (winner.chain_id.clone(), rest));
+    payouts.retain(|(_, amount)| *amount > Amount::ZERO);
+    Ok(payouts)
+}
+
+/// Eliminates the player the game has been waiting on for longer than the room's `turn_seconds`:
+/// the defender of a pending attack, or else the current attacker. Decided on the claimant's own copy,
+/// which is the one holding the pending attack; returns the eliminated player to report to the others.
+pub fn claim_timeout(room: &mut Room, claimant: &str, now: u64) -> Result<String, String> {
+    if room.game_state != GameState::InGame {
+        return Err("Game not in progress".into());
+    }
+    let Some(turn_seconds) = room.settings.turn_seconds else {
+        return Err("Room has no turn time limit".into());
+    };
+    let stalled = room
+        .pending_target
+        .clone()
+        .or_else(|| room.current_attacker.clone())
+        .ok_or_else(|| "Nobody to time out".to_string())?;
+    if !room.players.iter().any(|p| p.chain_id == claimant) {
+        return Err("Not a player in this room".into());
+    }
+    if stalled == claimant {
+        return Err("The game is waiting on you".into());
+    }
+    if now.saturating_sub(room.last_activity_at) <= turn_seconds as u64 * 1_000_000 {
+        return Err("Turn time has not run out yet".into());
+    }
+    if room.pending_target.as_deref() == Some(stalled.as_str()) {
+        room.pending_attack = None;
+        room.pending_salvo = None;
+        room.pending_weapon = None;
+        room.pending_target = None;
+    }
+    remove_player(room, &stalled);
+    finish_if_last_standing(room);
+    Ok(stalled)
+}
+
+/// Applies a timeout that `claimant` decided on its own chain, if this copy's turn state bears it
+/// out: the game must be waiting on `stalled` or, for a pending attack, on its answer to `claimant`.
+pub fn accept_timeout_claim(room: &mut Room, claimant: &str, stalled: &str) -> Result<(), String> {
+    if room.game_state != GameState::InGame {
+        return Err("Game not in progress".into());
+    }
+    if room.settings.turn_seconds.is_none() {
+        return Err("Room has no turn time limit".into());
+    }
+    let in_game = |chain_id: &str| {
+        room.players
+            .iter()
+            .any(|p| p.chain_id == chain_id && !p.eliminated)
+    };
+    if claimant == stalled || !in_game(claimant) || !in_game(stalled) {
+        return Err("Invalid timeout claim".into());
+    }
+    let attacker = room.current_attacker.as_deref();
+    if attacker != Some(stalled) && attacker != Some(claimant) {
+        return Err("The game is not waiting on that player".into());
+    }
+    remove_player(room, stalled);
+    finish_if_last_standing(room);
     Ok(())
 }
+
+/// A way to end a game early that every player still in it must agree to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Proposal {
+    /// Ends the current game without a winner; a series goes on to its next game.
+    Draw,
+    /// Ends the whole room as `Aborted`, without a winner.
+    Abort,
+}
+
+/// Records that a player proposes (`accepting == false`) or accepts an early end. Once every player
+/// still in the game agrees, ends the game or room and returns `true`.
+pub fn agree_to_end(
+    room: &mut Room,
+    proposal: Proposal,
+    chain_id: &str,
+    accepting: bool,
+) -> Result<bool, String> {
+    if room.status != RoomStatus::Active {
+        return Err("Room not active".into());
+    }
+    if proposal == Proposal::Draw && room.game_state != GameState::InGame {
+        return Err("Game not in progress".into());
+    }
+    if !room
+        .players
+        .iter()
+        .any(|p| p.chain_id == chain_id && !p.eliminated)
+    {
+        return Err("Not a player in this game".into());
+    }
+    let votes = match proposal {
+        Proposal::Draw => &mut room.draw_votes,
+        Proposal::Abort => &mut room.abort_votes,
+    };
+    if votes.iter().any(|v| v == chain_id) {
+        return Err("Already agreed".into());
+    }
+    if accepting && votes.is_empty() {
+        return Err("Nothing to accept".into());
+    }
+    votes.push(chain_id.to_string());
+    let votes = votes.clone();
+    if !room
+        .players
+        .iter()
+        .filter(|p| !p.eliminated)
+        .all(|p| votes.contains(&p.chain_id))
+    {
+        return Ok(false);
+    }
+    match proposal {
+        Proposal::Draw => end_game(room, None),
+        Proposal::Abort => {
+            room.game_state = GameState::Ended;
+            room.status = RoomStatus::Aborted;
+            room.winner_chain_id = None;
+        }
+    }
+    Ok(true)
+}
+
+/// Ends the room once the remaining fleets all belong to one side. The last one standing wins;
+/// in team rooms the winner is the first surviving member of the winning team.
+pub fn finish_if_last_standing(room: &mut Room) -> bool {
+    let mut alive = room.players.iter().filter(|p| !p.eliminated);
+    let first = alive.next();
+    if let Some(first) = first {
+        if alive.any(|p| !same_side(p, first)) {
+            return false;
+        }
+    }
+    let winner_chain_id = first.map(|p| p.chain_id.clone());
+    end_game(room, winner_chain_id);
+    true
+}
+
+/// Ends the current game. Outside a series this ends the room. In a series, the winner's side scores
+/// and the room stays active for `next_series_game` until a side has won the majority of the games, all
+/// games are played with a single leader, or only one side is left in the room.
+pub fn end_game(room: &mut Room, winner_chain_id: Option<String>) {
+    room.game_state = GameState::Ended;
+    if room.settings.best_of <= 1 {
+        room.winner_chain_id = winner_chain_id;
+        room.status = RoomStatus::Ended;
+        return;
+    }
+    if let Some(winner) = room
+        .players
+        .iter()
+        .find(|p| Some(&p.chain_id) == winner_chain_id.as_ref())
+        .cloned()
+    {
+        for player in room.players.iter_mut().filter(|p| same_side(p, &winner)) {
+            player.series_wins += 1;
+        }
+    }
+    let needed = room.settings.best_of / 2 + 1;
+    let leader = series_leader(room).map(|p| p.chain_id.clone());
+    let forfeited = one_side_left(room);
+    let decided = forfeited
+        || room.players.iter().any(|p| p.series_wins >= needed)
+        || (room.game_number >= room.settings.best_of && leader.is_some());
+    if decided {
+        room.status = RoomStatus::Ended;
+        room.winner_chain_id = if forfeited {
+            winner_chain_id
+        } else {
+            leader.or(winner_chain_id)
+        };
+    } else {
+        room.winner_chain_id = winner_chain_id;
+    }
+}
+
+/// The player with the most series wins, if nobody on another side has as many.
+pub fn series_leader(room: &Room) -> Option<&PlayerInfo> {
+    let best = room.players.iter().map(|p| p.series_wins).max()?;
+    let leader = room.players.iter().find(|p| p.series_wins == best)?;
+    room.players
+        .iter()
+        .filter(|p| p.series_wins == best)
+        .all(|p| same_side(p, leader))
+        .then_some(leader)
+}
+
+/// Resets a room whose series game just ended for the next game: every player places a new fleet.
+pub fn next_series_game(room: &mut Room) {
+    room.game_number += 1;
+    room.game_state = GameState::PlacingBoards;
+    room.current_attacker = None;
+    room.pending_attack = None;
+    room.pending_salvo = None;
+    room.pending_weapon = None;
+    room.pending_target = None;
+    room.skip_next_turn.clear();
+    room.draw_votes.clear();
+    for player in room.players.iter_mut() {
+        player.board_submitted = false;
+        player.ships_afloat = 0;
+        player.weapons_used.clear();
+        player.eliminated = false;
+    }
+}
+
+/// Who opens the current game: the seats take turns, starting with the host.
+pub fn series_first_attacker(room: &Room) -> Option<String> {
+    let seat = (room.game_number.max(1) as usize - 1) % room.players.len().max(1);
+    room.players.get(seat).map(|p| p.chain_id.clone())
+}
+
+pub fn mine_blast_target(board: &Board, seed: u64) -> Option<Coord> {
+    let candidates: Vec<Coord> = (0..board.size)
+        .flat_map(|row| (0..board.size).map(move |col| Coord { row, col }))
+        .filter(|c| {
+            let cell = &board.cells[idx(board.size, c.row, c.col)];
+            !cell.attacked && cell.terrain != Terrain::Island
+        })
+        .collect();
+    if candidates.is_empty() {
+        return None;
+    }
+    Some(candidates[(seed % candidates.len() as u64) as usize])
+}
+
+pub const MAX_SAVED_LAYOUTS: usize = 10;
+const MAX_LAYOUT_NAME_LEN: usize = 32;
+
+/// Saves a layout under its name, replacing an older one with the same name. Layouts are checked against the
+/// default rules here; the room's own rules apply when one is submitted.
+pub fn save_layout(layouts: &mut Vec<SavedLayout>, layout: SavedLayout) -> Result<(), String> {
+    if layout.name.trim().is_empty() || layout.name.len() > MAX_LAYOUT_NAME_LEN {
+        return Err(format!(
+            "Layout name must have 1 to {} characters",
+            MAX_LAYOUT_NAME_LEN
+        ));
+    }
+    validate_and_build_board(
+        BOARD_SIZE,
+        &layout.ships,
+        &[],
+        &[],
+        &RoomSettings::default(),
+    )?;
+    match layouts.iter().position(|l| l.name == layout.name) {
+        Some(pos) => layouts[pos] = layout,
+        None if layouts.len() >= MAX_SAVED_LAYOUTS => {
+            return Err(format!(
+                "At most {} layouts can be saved",
+                MAX_SAVED_LAYOUTS
+            ));
+        }
+        None => layouts.push(layout),
+    }
+    Ok(())
+}
+
+/// Side of every board; rooms, listings and matchmaking all use it.
+pub const BOARD_SIZE: u8 = 10;
+
+/// Ship lengths of the standard fleet, as offered by the frontend.
+pub const STANDARD_FLEET: [u8; 5] = [5, 4, 3, 3, 2];
+
+/// Deterministic seed from a chain id and a block height, so that contract and service agree on it.
+pub fn chain_seed(chain_id: &str, block_height: u64) -> u64 {
+    fnv1a(chain_id.as_bytes()) ^ block_height
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325u64, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01B3)
+    })
+}
+
+/// SplitMix64 step; good enough to spread the chain-derived seeds used for bot decisions.
+fn next_random(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Places straight ships of the given lengths at random under the default room rules, checking
+/// every ship with `validate_and_build_board`. Use `random_board_placements` for a room's own
+/// placement rule and islands.
+pub fn generate_random_board(
+    size: u8,
+    fleet: &[u8],
+    seed: u64,
+) -> Result<Vec<ShipPlacementInput>, String> {
+    random_board_placements(size, fleet, &RoomSettings::default(), seed)
+}
+
+/// Like `generate_random_board`, but under a room's placement rule and islands.
+pub fn random_board_placements(
+    size: u8,
+    fleet: &[u8],
+    settings: &RoomSettings,
+    seed: u64,
+) -> Result<Vec<ShipPlacementInput>, String> {
+    // Partial fleets are checked along the way, so the fleet rule itself is left out.
+    let rules = RoomSettings {
+        fleet: Vec::new(),
+        ..settings.clone()
+    };
+    let mut rng = seed;
+    for _ in 0..50 {
+        let mut placements: Vec<ShipPlacementInput> = Vec::with_capacity(fleet.len());
+        for &length in fleet {
+            if length == 0 || length > size {
+                return Err("Ship does not fit on the board".into());
+            }
+            for _ in 0..200 {
+                let axis = if next_random(&mut rng).is_multiple_of(2) {
+                    Axis::Horiz
+                } else {
+                    Axis::Vert
+                };
+                let span = (size - length + 1) as u64;
+                let (row, col) = match axis {
+                    Axis::Horiz => (
+                        next_random(&mut rng) % size as u64,
+                        next_random(&mut rng) % span,
+                    ),
+                    Axis::Vert => (
+                        next_random(&mut rng) % span,
+                        next_random(&mut rng) % size as u64,
+                    ),
+                };
+                placements.push(ShipPlacementInput {
+                    row: row as u8,
+                    col: col as u8,
+                    length,
+                    axis,
+                });
+                if validate_and_build_board(size, &placements, &[], &[], &rules).is_ok() {
+                    break;
+                }
+                placements.pop();
+            }
+        }
+        if placements.len() == fleet.len() {
+            return Ok(placements);
+        }
+    }
+    Err("Could not place the fleet".into())
+}
+
+/// Bot targeting. Wounded ships are finished first, extending a line of hits before probing
+/// around a lone hit; otherwise the bot hunts on a checkerboard, which every ship of length 2+ crosses.
+pub fn choose_bot_shot(view: &EnemyBoardView, seed: u64) -> Option<Coord> {
+    let size = view.size as i16;
+    let cell = |r: i16, c: i16| {
+        if r < 0 || c < 0 || r >= size || c >= size {
+            return None;
+        }
+        view.cells.get((r * size + c) as usize).copied()
+    };
+    let coord = |r: i16, c: i16| Coord {
+        row: r as u8,
+        col: c as u8,
+    };
+    let mut line = Vec::new();
+    let mut around = Vec::new();
+    for r in 0..size {
+        for c in 0..size {
+            if cell(r, c) != Some(EnemyCell::Hit) {
+                continue;
+            }
+            for &(dr, dc) in &EDGE_NEIGHBOURS {
+                let (nr, nc) = (r + dr as i16, c + dc as i16);
+                if cell(nr, nc) != Some(EnemyCell::Unknown) {
+                    continue;
+                }
+                if cell(r - dr as i16, c - dc as i16) == Some(EnemyCell::Hit) {
+                    line.push(coord(nr, nc));
+                } else {
+                    around.push(coord(nr, nc));
+                }
+            }
+        }
+    }
+    let unknown: Vec<Coord> = (0..size)
+        .flat_map(|r| (0..size).map(move |c| (r, c)))
+        .filter(|&(r, c)| cell(r, c) == Some(EnemyCell::Unknown))
+        .map(|(r, c)| coord(r, c))
+        .collect();
+    let parity: Vec<Coord> = unknown
+        .iter()
+        .copied()
+        .filter(|c| (c.row + c.col) % 2 == 0)
+        .collect();
+    [line, around, parity, unknown]
+        .into_iter()
+        .find(|candidates| !candidates.is_empty())
+        .map(|candidates| candidates[(seed % candidates.len() as u64) as usize])
+}
+
+/// Up to `count` distinct bot shots for a salvo, picked one after another as if each earlier one missed.
+pub fn choose_bot_salvo(view: &EnemyBoardView, count: u8, seed: u64) -> Vec<Coord> {
+    let mut scratch = view.clone();
+    let mut shots = Vec::new();
+    for i in 0..count as u64 {
+        let Some(shot) = choose_bot_shot(&scratch, seed.wrapping_add(i)) else {
+            break;
+        };
+        scratch.cells[shot.row as usize * scratch.size as usize + shot.col as usize] =
+            EnemyCell::Miss;
+        shots.push(shot);
+    }
+    shots
+}
+
+/// Whether a bot may fire now: the game runs, it is the bot's turn and no shot of its own is pending.
+pub fn bot_can_fire(room: &Room, bot_chain_id: &str) -> bool {
+    room.game_state == GameState::InGame
+        && room.current_attacker.as_deref() == Some(bot_chain_id)
+        && room.pending_attack.is_none()
+        && room.pending_salvo.is_none()
+        && room.pending_weapon.is_none()
+}
+
+/// Extra weight of a placement for each unresolved hit it covers, so that wounded ships are hunted down first.
+const HIT_WEIGHT: u32 = 10;
+
+/// Probability density of the remaining straight ships of `fleet` over the unknown cells of `view`.
+/// A placement may only cover unknown or hit cells, and must keep clear of sunk ships under `rule`.
+pub fn target_heatmap(view: &EnemyBoardView, fleet: &[u8], rule: PlacementRule) -> Vec<u32> {
+    let size = view.size as i16;
+    let in_bounds = |r: i16, c: i16| r >= 0 && c >= 0 && r < size && c < size;
+    let at = |r: i16, c: i16| view.cells.get((r * size + c) as usize).copied();
+    let open: Vec<bool> = (0..view.cells.len() as i16)
+        .map(|i| {
+            let (r, c) = (i / size, i % size);
+            matches!(at(r, c), Some(EnemyCell::Unknown | EnemyCell::Hit))
+                && !rule.blocked_neighbours().iter().any(|&(dr, dc)| {
+                    let (nr, nc) = (r + dr as i16, c + dc as i16);
+                    in_bounds(nr, nc) && at(nr, nc) == Some(EnemyCell::Sunk)
+                })
+        })
+        .collect();
+    let mut weights = vec![0u32; view.cells.len()];
+    for &length in fleet {
+        let length = length as i16;
+        let axes: &[(i16, i16)] = if length == 1 {
+            &[(0, 1)]
+        } else {
+            &[(0, 1), (1, 0)]
+        };
+        for &(dr, dc) in axes {
+            for r in 0..size {
+                for c in 0..size {
+                    if length == 0 || !in_bounds(r + dr * (length - 1), c + dc * (length - 1)) {
+                        continue;
+                    }
+                    let cells: Vec<usize> = (0..length)
+                        .map(|i| ((r + dr * i) * size + (c + dc * i)) as usize)
+                        .collect();
+                    if !cells.iter().all(|&i| open.get(i) == Some(&true)) {
+                        continue;
+                    }
+                    let hits = cells
+                        .iter()
+                        .filter(|&&i| view.cells[i] == EnemyCell::Hit)
+                        .count() as u32;
+                    for &i in &cells {
+                        if view.cells[i] == EnemyCell::Unknown {
+                            weights[i] += 1 + HIT_WEIGHT * hits;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    weights
+}
+
+/// Ships of `fleet` not yet accounted for by `sunk`.
+pub fn fleet_remaining(fleet: &[u8], sunk: &[u8]) -> Vec<u8> {
+    let mut remaining = fleet.to_vec();
+    for ship in sunk {
+        if let Some(pos) = remaining.iter().position(|s| s == ship) {
+            remaining.remove(pos);
+        }
+    }
+    remaining
+}
+
+pub fn enemy_fleet_status(view: &EnemyBoardView, settings: &RoomSettings) -> EnemyFleetStatus {
+    EnemyFleetStatus {
+        owner_chain_id: view.owner_chain_id.clone(),
+        sunk: view.sunk_ships.clone(),
+        remaining: (!settings.fleet.is_empty())
+            .then(|| fleet_remaining(&settings.fleet, &view.sunk_ships)),
+    }
+}
+
+pub fn fleet_status(board: &Board) -> Vec<ShipStatus> {
+    board
+        .ships
+        .iter()
+        .map(|ship| {
+            let hits = ship
+                .cells
+                .iter()
+                .filter(|c| board.cells[idx(board.size, c.row, c.col)].attacked)
+                .count() as u8;
+            ShipStatus {
+                id: ship.id,
+                size: ship.cells.len() as u8,
+                hits,
+                sunk: hits as usize == ship.cells.len(),
+            }
+        })
+        .collect()
+}
+
+pub fn salvo_shot_count(room: &Room, chain_id: &str) -> u8 {
+    if let Some(shots) = room.settings.salvo_shots {
+        return shots;
+    }
+    room.players
+        .iter()
+        .find(|p| p.chain_id == chain_id)
+        .map(|p| p.ships_afloat)
+        .unwrap_or(0)
+}
+
+pub fn weapons_remaining(room: &Room, chain_id: &str) -> WeaponLoadout {
+    let used = room
+        .players
+        .iter()
+        .find(|p| p.chain_id == chain_id)
+        .map(|p| p.weapons_used.as_slice())
+        .unwrap_or_default();
+    let left = |weapon: Weapon| {
+        let spent = used.iter().filter(|w| **w == weapon).count();
+        room.settings
+            .weapons
+            .uses(weapon)
+            .saturating_sub(spent as u8)
+    };
+    WeaponLoadout {
+        sonar: left(Weapon::Sonar),
+        cross_bomb: left(Weapon::CrossBomb),
+        torpedo: left(Weapon::Torpedo),
+    }
+}
+
+pub fn record_weapon_use(room: &mut Room, chain_id: &str, weapon: Weapon) {
+    if let Some(p) = room.players.iter_mut().find(|p| p.chain_id == chain_id) {
+        p.weapons_used.push(weapon);
+    }
+}
+
+pub fn record_ship_sunk(room: &mut Room, chain_id: &str) {
+    if let Some(p) = room.players.iter_mut().find(|p| p.chain_id == chain_id) {
+        p.ships_afloat = p.ships_afloat.saturating_sub(1);
+        if p.ships_afloat == 0 {
+            p.eliminated = true;
+        }
+    }
+}
+
+pub fn record_shot(view: &mut EnemyBoardView, shot: &ShotResult) {
+    if shot.sunk {
+        set_enemy_view_cell(view, shot.row, shot.col, EnemyCell::Sunk).ok();
+        if let Some(cells) = shot.sunk_ship_cells.as_ref() {
+            view.sunk_ships.push(cells.len() as u8);
+            for c in cells {
+                set_enemy_view_cell(view, c.row, c.col, EnemyCell::Sunk).ok();
+            }
+        }
+        if let Some(adj) = shot.adjacent_coords.as_ref() {
+            for c in adj {
+                let index = idx(view.size, c.row, c.col);
+                if index < view.cells.len() && view.cells[index] == EnemyCell::Unknown {
+                    set_enemy_view_cell(view, c.row, c.col, EnemyCell::Miss).ok();
+                }
+            }
+        }
+    } else if shot.hit {
+        set_enemy_view_cell(view, shot.row, shot.col, EnemyCell::Hit).ok();
+    } else if shot.mine {
+        set_enemy_view_cell(view, shot.row, shot.col, EnemyCell::Mine).ok();
+    } else {
+        set_enemy_view_cell(view, shot.row, shot.col, EnemyCell::Miss).ok();
+    }
+}
+
+pub fn set_enemy_view_cell(view: &mut EnemyBoardView, row: u8, col: u8, value: EnemyCell) -> Result<(), String> {
+    let max_index = view.size.saturating_sub(1);
+    if row > max_index || col > max_index {
+        return Err("Coord out of bounds".into());
+    }
+    let index = idx(view.size, row, col);
+    view.cells[index] = value;
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+struct JoinCode {
+    room_id: String,
+    code: String,
+}
+
+impl BcsHashable<'_> for JoinCode {}
+
+/// Join codes are only kept hashed, salted with the room id.
+pub fn hash_join_code(room_id: &str, code: &str) -> CryptoHash {
+    CryptoHash::new(&JoinCode {
+        room_id: room_id.to_string(),
+        code: code.to_string(),
+    })
+}
+
+pub fn check_join_code(
+    room_id: &str,
+    code_hash: Option<&CryptoHash>,
+    code: Option<&str>,
+) -> Result<(), String> {
+    match (code_hash, code) {
+        (None, _) => Ok(()),
+        (Some(_), None) => Err("This room is private; a join code is required".into()),
+        (Some(hash), Some(code)) if hash_join_code(room_id, code) == *hash => Ok(()),
+        (Some(_), Some(_)) => Err("Wrong join code".into()),
+    }
+}
+
+pub fn preferences_compatible(a: &MatchPreferences, b: &MatchPreferences) -> bool {
+    let mut fleet_a = a.fleet.clone();
+    let mut fleet_b = b.fleet.clone();
+    fleet_a.sort_unstable();
+    fleet_b.sort_unstable();
+    a.board_size == b.board_size
+        && a.game_mode == b.game_mode
+        && fleet_a == fleet_b
+        && (a.turn_rule.is_none() || b.turn_rule.is_none() || a.turn_rule == b.turn_rule)
+        && a.ranked == b.ranked
+        && a.turn_seconds == b.turn_seconds
+}
+
+/// Room settings for a match between two players with compatible preferences.
+pub fn match_settings(a: &MatchPreferences, b: &MatchPreferences) -> RoomSettings {
+    RoomSettings {
+        game_mode: a.game_mode,
+        fleet: a.fleet.clone(),
+        turn_rule: a.turn_rule.or(b.turn_rule).unwrap_or_default(),
+        target_hints: !a.ranked,
+        ranked: a.ranked,
+        turn_seconds: a.turn_seconds,
+        ..RoomSettings::default()
+    }
+}
+
+/// How many recent match waits the orchestrator keeps for its estimates.
+pub const RECENT_MATCH_WAITS: usize = 20;
+
+pub fn record_match_wait(waits: &mut Vec<u64>, wait_micros: u64) {
+    waits.push(wait_micros);
+    if waits.len() > RECENT_MATCH_WAITS {
+        waits.remove(0);
+    }
+}
+
+pub fn average_wait_secs(waits: &[u64]) -> Option<u64> {
+    if waits.is_empty() {
+        return None;
+    }
+    Some(waits.iter().sum::<u64>() / waits.len() as u64 / 1_000_000)
+}
+
+pub fn queue_stats(queue: &[MatchmakingPlayer], waits: &[u64]) -> MatchmakingQueueStats {
+    let mut by_mode: Vec<ModeQueueCount> = Vec::new();
+    for player in queue {
+        let (game_mode, ranked) = (player.preferences.game_mode, player.preferences.ranked);
+        match by_mode
+            .iter_mut()
+            .find(|m| m.game_mode == game_mode && m.ranked == ranked)
+        {
+            Some(count) => count.players += 1,
+            None => by_mode.push(ModeQueueCount {
+                game_mode,
+                ranked,
+                players: 1,
+            }),
+        }
+    }
+    MatchmakingQueueStats {
+        size: queue.len() as u32,
+        average_wait_secs: average_wait_secs(waits),
+        by_mode,
+    }
+}
+
+/// Removes the players who waited longer than `stale_wait_secs` and have not visited every shard yet.
+/// Returns the index of the first one removed (the queue length if none) and the removed players.
+pub fn take_stale_waiters(
+    queue: &mut Vec<MatchmakingPlayer>,
+    now: u64,
+    stale_wait_secs: u64,
+    shard_count: usize,
+) -> (usize, Vec<MatchmakingPlayer>) {
+    let is_stale = |p: &MatchmakingPlayer| {
+        stale_wait_secs > 0
+            && (p.hops as usize) + 1 < shard_count
+            && now.saturating_sub(p.enqueued_at) > stale_wait_secs * 1_000_000
+    };
+    let first = queue.iter().position(is_stale).unwrap_or(queue.len());
+    let (stale, kept) = queue.drain(..).partition(is_stale);
+    *queue = kept;
+    (first, stale)
+}
+
+pub fn validate_preferences(preferences: &MatchPreferences) -> Result<(), String> {
+    if preferences.board_size != BOARD_SIZE {
+        return Err("Only 10x10 boards are supported".into());
+    }
+    validate_room_settings(
+        preferences.board_size,
+        &match_settings(preferences, preferences),
+    )
+}
+
+/// Whether a room still takes players and may stay in the lobby.
+pub fn is_open_for_listing(room: &Room) -> bool {
+    room.status == RoomStatus::Active
+        && matches!(
+            room.game_state,
+            GameState::WaitingForPlayer | GameState::PlacingBoards
+        )
+        && room.players.len() < room.settings.max_players as usize
+}
+
+pub fn room_listing(room: &Room, room_name: &str, listed_at: String) -> RoomListing {
+    RoomListing {
+        room_id: room.room_id.clone(),
+        room_name: room_name.to_string(),
+        host_chain_id: room.host_chain_id.clone(),
+        host_name: room
+            .players
+            .iter()
+            .find(|p| p.chain_id == room.host_chain_id)
+            .map(|p| p.name.clone())
+            .unwrap_or_default(),
+        board_size: BOARD_SIZE,
+        settings: room.settings.clone(),
+        private: room.private,
+        players: room.players.len() as u8,
+        listed_at,
+    }
+}
+
+pub fn listing_matches(listing: &RoomListing, filter: &LobbyFilter) -> bool {
+    let name_matches = filter.name_contains.as_ref().is_none_or(|name| {
+        listing
+            .room_name
+            .to_lowercase()
+            .contains(&name.to_lowercase())
+    });
+    filter
+        .game_mode
+        .is_none_or(|mode| listing.settings.game_mode == mode)
+        && filter
+            .max_players
+            .is_none_or(|max| listing.settings.max_players == max)
+        && filter
+            .teams
+            .is_none_or(|teams| listing.settings.teams == teams)
+        && !(filter.hide_private && listing.private)
+        && name_matches
+}
+
+pub fn new_tournament(
+    tournament_id: String,
+    name: String,
+    format: TournamentFormat,
+    settings: RoomSettings,
+    max_players: u32,
+    rounds: Option<u32>,
+) -> Result<Tournament, String> {
+    if name.trim().is_empty() {
+        return Err("Tournament name cannot be empty".into());
+    }
+    if settings.max_players != 2 || settings.teams {
+        return Err("Tournament matches are duels".into());
+    }
+    if settings.stake > Amount::ZERO {
+        return Err("Tournament matches cannot be staked".into());
+    }
+    validate_room_settings(BOARD_SIZE, &settings)?;
+    if !(2..=64).contains(&max_players) {
+        return Err("Tournaments take between 2 and 64 players".into());
+    }
+    if rounds == Some(0) {
+        return Err("A Swiss tournament needs at least one round".into());
+    }
+    Ok(Tournament {
+        tournament_id,
+        name,
+        format,
+        settings,
+        max_players,
+        rounds: rounds.unwrap_or(0),
+        status: TournamentStatus::Registration,
+        round: 0,
+        players: Vec::new(),
+        matches: Vec::new(),
+        champion_chain_id: None,
+    })
+}
+
+pub fn register_tournament_player(
+    tournament: &mut Tournament,
+    chain_id: &str,
+    name: String,
+) -> Result<(), String> {
+    if tournament.status != TournamentStatus::Registration {
+        return Err("Registration is closed".into());
+    }
+    if tournament.players.iter().any(|p| p.chain_id == chain_id) {
+        return Err("Already registered".into());
+    }
+    if tournament.players.len() >= tournament.max_players as usize {
+        return Err("Tournament is full".into());
+    }
+    tournament.players.push(TournamentPlayer {
+        chain_id: chain_id.to_string(),
+        name,
+        wins: 0,
+        losses: 0,
+        eliminated: false,
+        had_bye: false,
+        opponents: Vec::new(),
+    });
+    Ok(())
+}
+
+/// Closes registration and pairs the first round. Returns the matches to play.
+pub fn start_tournament(tournament: &mut Tournament) -> Result<Vec<TournamentMatch>, String> {
+    if tournament.status != TournamentStatus::Registration {
+        return Err("Tournament already started".into());
+    }
+    if tournament.players.len() < 2 {
+        return Err("A tournament needs at least two players".into());
+    }
+    if tournament.format == TournamentFormat::Swiss && tournament.rounds == 0 {
+        // Enough rounds for a single undefeated player: ceil(log2(players)).
+        tournament.rounds = (tournament.players.len() as u32)
+            .next_power_of_two()
+            .trailing_zeros();
+    }
+    tournament.status = TournamentStatus::Running;
+    Ok(start_next_round(tournament))
+}
+
+/// Players ordered by wins, then losses, then registration.
+pub fn tournament_standings(tournament: &Tournament) -> Vec<TournamentPlayer> {
+    let mut standings = tournament.players.clone();
+    standings.sort_by(|a, b| b.wins.cmp(&a.wins).then(a.losses.cmp(&b.losses)));
+    standings
+}
+
+/// Records the winner of the current-round match hosted by `host_chain_id`. Once the round is complete,
+/// either finishes the tournament or pairs the next round, whose matches are returned.
+pub fn record_tournament_result(
+    tournament: &mut Tournament,
+    host_chain_id: &str,
+    winner_chain_id: &str,
+) -> Result<Vec<TournamentMatch>, String> {
+    if tournament.status != TournamentStatus::Running {
+        return Err("Tournament is not running".into());
+    }
+    let round = tournament.round;
+    let game = tournament
+        .matches
+        .iter_mut()
+        .find(|m| {
+            m.round == round && m.host_chain_id == host_chain_id && m.winner_chain_id.is_none()
+        })
+        .ok_or_else(|| "No open match hosted by this chain".to_string())?;
+    let guest_chain_id = game.guest_chain_id.clone().unwrap_or_default();
+    let loser_chain_id = if winner_chain_id == host_chain_id {
+        guest_chain_id
+    } else if winner_chain_id == guest_chain_id {
+        host_chain_id.to_string()
+    } else {
+        return Err("Winner did not play this match".into());
+    };
+    game.winner_chain_id = Some(winner_chain_id.to_string());
+    let elimination = tournament.format == TournamentFormat::SingleElimination;
+    for player in tournament.players.iter_mut() {
+        if player.chain_id == winner_chain_id {
+            player.wins += 1;
+        } else if player.chain_id == loser_chain_id {
+            player.losses += 1;
+            player.eliminated = elimination;
+        }
+    }
+
+    if tournament
+        .matches
+        .iter()
+        .any(|m| m.round == round && m.winner_chain_id.is_none())
+    {
+        return Ok(Vec::new());
+    }
+    let survivors = tournament.players.iter().filter(|p| !p.eliminated).count();
+    let finished = if elimination {
+        survivors <= 1
+    } else {
+        round >= tournament.rounds
+    };
+    if finished {
+        tournament.status = TournamentStatus::Finished;
+        tournament.champion_chain_id = tournament_standings(tournament)
+            .into_iter()
+            .find(|p| !p.eliminated)
+            .map(|p| p.chain_id);
+        return Ok(Vec::new());
+    }
+    Ok(start_next_round(tournament))
+}
+
+/// Pairs the next round. Single elimination pairs the survivors in seed order; Swiss pairs players
+/// with the closest standing they have not met yet. An odd player out gets a bye, which counts as a win.
+fn start_next_round(tournament: &mut Tournament) -> Vec<TournamentMatch> {
+    tournament.round += 1;
+    let round = tournament.round;
+    let mut order: Vec<TournamentPlayer> = match tournament.format {
+        TournamentFormat::SingleElimination => tournament
+            .players
+            .iter()
+            .filter(|p| !p.eliminated)
+            .cloned()
+            .collect(),
+        TournamentFormat::Swiss => tournament_standings(tournament),
+    };
+
+    let mut matches = Vec::new();
+    if !order.len().is_multiple_of(2) {
+        // The top seed without a bye skips a round of single elimination; in Swiss the lowest one does.
+        let bye = match tournament.format {
+            TournamentFormat::SingleElimination => {
+                order.iter().position(|p| !p.had_bye).unwrap_or(0)
+            }
+            TournamentFormat::Swiss => order
+                .iter()
+                .rposition(|p| !p.had_bye)
+                .unwrap_or(order.len() - 1),
+        };
+        let player = order.remove(bye);
+        matches.push(TournamentMatch {
+            round,
+            host_chain_id: player.chain_id.clone(),
+            guest_chain_id: None,
+            winner_chain_id: Some(player.chain_id),
+        });
+    }
+    while !order.is_empty() {
+        let host = order.remove(0);
+        let partner = match tournament.format {
+            TournamentFormat::SingleElimination => 0,
+            TournamentFormat::Swiss => order
+                .iter()
+                .position(|p| !host.opponents.contains(&p.chain_id))
+                .unwrap_or(0),
+        };
+        let guest = order.remove(partner);
+        matches.push(TournamentMatch {
+            round,
+            host_chain_id: host.chain_id,
+            guest_chain_id: Some(guest.chain_id),
+            winner_chain_id: None,
+        });
+    }
+
+    for game in &matches {
+        for player in tournament.players.iter_mut() {
+            let Some(guest) = &game.guest_chain_id else {
+                if player.chain_id == game.host_chain_id {
+                    player.wins += 1;
+                    player.had_bye = true;
+                }
+                continue;
+            };
+            if player.chain_id == game.host_chain_id {
+                player.opponents.push(guest.clone());
+            } else if &player.chain_id == guest {
+                player.opponents.push(game.host_chain_id.clone());
+            }
+        }
+    }
+    tournament.matches.extend(matches.iter().cloned());
+    matches.retain(|m| m.guest_chain_id.is_some());
+    matches
+}
+
+/// What a chain undoes after one of its tracked messages bounced back.
+#[derive(Debug, Default, PartialEq)]
+pub struct BounceRollback {
+    /// The local room copy changed and must be stored again.
+    pub room_changed: bool,
+    /// Stake the host must pay back to a player it had already seated.
+    pub refund: Option<(String, Amount)>,
+    pub notification: Option<String>,
+}
+
+/// Rolls the local room copy back after `message` was rejected by `rejected_by`. Join requests are
+/// left to the caller, since they only concern `pending_join`.
+pub fn roll_back_bounced_message(
+    room: Option<&mut Room>,
+    message: &CrossChainMessage,
+    self_chain: &str,
+    rejected_by: &str,
+) -> BounceRollback {
+    let mut rollback = BounceRollback::default();
+    match message {
+        CrossChainMessage::InitialStateSync { room: synced } => {
+            let Some(room) = room else {
+                return rollback;
+            };
+            if room.room_id != synced.room_id || room.game_state != GameState::PlacingBoards {
+                return rollback;
+            }
+            rollback.refund =
+                take_stake(room, rejected_by).map(|stake| (rejected_by.to_string(), stake));
+            room.players.retain(|p| p.chain_id != rejected_by);
+            assign_teams(room);
+            if room.players.len() < 2 {
+                room.game_state = GameState::WaitingForPlayer;
+            }
+            rollback.room_changed = true;
+            rollback.notification = Some(format!("Player {} could not join the room", rejected_by));
+        }
+        CrossChainMessage::BoardSubmittedNotice { .. } => {
+            if let Some(p) =
+                room.and_then(|r| r.players.iter_mut().find(|p| p.chain_id == self_chain))
+            {
+                p.board_submitted = false;
+                rollback.room_changed = true;
+            }
+            rollback.notification = Some("Board submission rejected by host".to_string());
+        }
+        CrossChainMessage::AttackRequest { row, col, .. } => {
+            if let Some(room) = room.filter(|r| {
+                r.pending_attack
+                    == Some(Coord {
+                        row: *row,
+                        col: *col,
+                    })
+            }) {
+                room.pending_attack = None;
+                room.pending_target = None;
+                rollback.room_changed = true;
+            }
+            rollback.notification = Some(format!("Attack at ({},{}) was rejected", row, col));
+        }
+        CrossChainMessage::SalvoAttackRequest { shots, .. } => {
+            if let Some(room) = room.filter(|r| r.pending_salvo.as_ref() == Some(shots)) {
+                room.pending_salvo = None;
+                room.pending_target = None;
+                rollback.room_changed = true;
+            }
+            rollback.notification = Some(format!("Salvo of {} shots was rejected", shots.len()));
+        }
+        CrossChainMessage::SalvoRevealResult { .. } => {
+            rollback.notification = Some("Salvo reveal was rejected by the attacker".to_string());
+        }
+        CrossChainMessage::WeaponAttackRequest { shot, .. } => {
+            if let Some(room) = room.filter(|r| r.pending_weapon == Some(*shot)) {
+                room.pending_weapon = None;
+                room.pending_target = None;
+                rollback.room_changed = true;
+            }
+            rollback.notification = Some(format!(
+                "{:?} at ({},{}) was rejected",
+                shot.weapon, shot.row, shot.col
+            ));
+        }
+        CrossChainMessage::WeaponRevealResult { shot, .. } => {
+            rollback.notification = Some(format!(
+                "{:?} reveal was rejected by the attacker",
+                shot.weapon
+            ));
+        }
+        CrossChainMessage::RevealResult { row, col, .. } => {
+            rollback.notification = Some(format!(
+                "Reveal for ({},{}) was rejected by the attacker",
+                row, col
+            ));
+        }
+        CrossChainMessage::TimeoutClaim { .. } => {
+            rollback.notification = Some(format!("Timeout claim was rejected by {}", rejected_by));
+        }
+        CrossChainMessage::LeaveNotice { .. } => {
+            // The leaver has already dropped the room; there is nothing left to restore.
+            rollback.notification = Some("Leave notice was rejected".to_string());
+        }
+        CrossChainMessage::MineBlast { shot, .. } => {
+            rollback.notification = Some(format!(
+                "Mine blast report for ({},{}) was rejected",
+                shot.row, shot.col
+            ));
+        }
+        _ => {}
+    }
+    rollback
+}
+
+pub fn authenticate_sender(origin: Option<ChainId>, claimed: ChainId) -> Result<(), String> {
+    match origin {
+        Some(origin) if origin == claimed => Ok(()),
+        Some(_) => Err("Message origin does not match claimed sender".into()),
+        None => Err("Not executing an incoming message".into()),
+    }
+}
+
+pub fn authenticate_orchestrator(
+    parameters: &BattleshipParameters,
+    origin: Option<ChainId>,
+) -> Result<(), String> {
+    match origin {
+        Some(origin) if parameters.is_orchestrator(origin) => Ok(()),
+        Some(_) => Err("Message does not come from an official orchestrator".into()),
+        None => Err("Not executing an incoming message".into()),
+    }
+}
+
+pub fn authenticate_room_player(
+    room: &Room,
+    origin: Option<ChainId>,
+    claimed: ChainId,
+) -> Result<(), String> {
+    authenticate_sender(origin, claimed)?;
+    let claimed = claimed.to_string();
+    if !room.players.iter().any(|p| p.chain_id == claimed) {
+        return Err("Sender is not a player in this room".into());
+    }
+    Ok(())
+}
+
+pub fn authenticate_room_host(room: &Room, origin: Option<ChainId>) -> Result<(), String> {
+    let host: ChainId = room
+        .host_chain_id
+        .parse()
+        .map_err(|_| "Invalid host chain ID".to_string())?;
+    authenticate_sender(origin, host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(n: u8) -> ChainId {
+        format!("{:064x}", n).parse().unwrap()
+    }
+
+    fn room_with(host: ChainId, guest: ChainId) -> Room {
+        Room {
+            room_id: "1".into(),
+            host_chain_id: host.to_string(),
+            status: RoomStatus::Active,
+            game_state: GameState::InGame,
+            settings: RoomSettings::default(),
+            private: false,
+            players: vec![
+                PlayerInfo {
+                    chain_id: host.to_string(),
+                    name: "host".into(),
+                    board_submitted: true,
+                    ships_afloat: 2,
+                    weapons_used: Vec::new(),
+                    eliminated: false,
+                    team: None,
+                    series_wins: 0,
+                },
+                PlayerInfo {
+                    chain_id: guest.to_string(),
+                    name: "guest".into(),
+                    board_submitted: true,
+                    ships_afloat: 2,
+                    weapons_used: Vec::new(),
+                    eliminated: false,
+                    team: None,
+                    series_wins: 0,
+                },
+            ],
+            current_attacker: Some(host.to_string()),
+            pending_attack: None,
+            pending_salvo: None,
+            pending_weapon: None,
+            pending_target: None,
+            skip_next_turn: Vec::new(),
+            winner_chain_id: None,
+            game_number: 1,
+            stakes: Vec::new(),
+            draw_votes: Vec::new(),
+            abort_votes: Vec::new(),
+            last_activity_at: 0,
+        }
+    }
+
+    #[test]
+    fn accepts_message_from_room_player() {
+        let room = room_with(chain(1), chain(2));
+        assert!(authenticate_room_player(&room, Some(chain(2)), chain(2)).is_ok());
+    }
+
+    #[test]
+    fn rejects_forged_leave_notice_from_outsider() {
+        // A third chain claims to be the guest in order to end the room.
+        let room = room_with(chain(1), chain(2));
+        assert!(authenticate_room_player(&room, Some(chain(3)), chain(2)).is_err());
+    }
+
+    #[test]
+    fn rejects_outsider_claiming_own_chain() {
+        let room = room_with(chain(1), chain(2));
+        assert!(authenticate_room_player(&room, Some(chain(3)), chain(3)).is_err());
+    }
+
+    #[test]
+    fn rejects_forged_attack_request_between_players() {
+        // The host cannot send an attack in the guest's name.
+        let room = room_with(chain(1), chain(2));
+        assert!(authenticate_room_player(&room, Some(chain(1)), chain(2)).is_err());
+    }
+
+    #[test]
+    fn leaving_mid_series_forfeits_the_series() {
+        let mut room = room_with(chain(1), chain(2));
+        room.settings.best_of = 3;
+        room.players[1].series_wins = 1;
+        remove_player(&mut room, &chain(2).to_string());
+        assert!(finish_if_last_standing(&mut room));
+        assert_eq!(room.players.len(), 1);
+        assert_eq!(room.status, RoomStatus::Ended);
+        assert_eq!(room.winner_chain_id, Some(chain(1).to_string()));
+
+        // In a team series, a side that walks out between games loses, and teams are not reshuffled.
+        let mut teams = room_with(chain(1), chain(2));
+        for n in 3..=4 {
+            let mut player = teams.players[1].clone();
+            player.chain_id = chain(n).to_string();
+            teams.players.push(player);
+        }
+        teams.settings.best_of = 3;
+        teams.settings.teams = true;
+        assign_teams(&mut teams);
+        teams.game_number = 2;
+        teams.game_state = GameState::PlacingBoards;
+        remove_player(&mut teams, &chain(2).to_string());
+        assert!(!finish_if_last_standing(&mut teams));
+        assert_eq!(
+            teams.players.iter().map(|p| p.team).collect::<Vec<_>>(),
+            vec![Some(0), Some(0), Some(1)]
+        );
+        remove_player(&mut teams, &chain(4).to_string());
+        assert!(finish_if_last_standing(&mut teams));
+        assert_eq!(teams.status, RoomStatus::Ended);
+        assert_eq!(teams.winner_chain_id, Some(chain(1).to_string()));
+    }
+
+    #[test]
+    fn leaving_forfeits_only_to_a_single_remaining_side() {
+        let mut room = room_with(chain(1), chain(2));
+        assert_eq!(
+            forfeit_winner(&room, &chain(1).to_string()),
+            Some(chain(2).to_string())
+        );
+
+        let mut third = room.players[1].clone();
+        third.chain_id = chain(3).to_string();
+        room.players.push(third);
+        assert_eq!(forfeit_winner(&room, &chain(1).to_string()), None);
+        room.players[2].eliminated = true;
+        assert_eq!(
+            forfeit_winner(&room, &chain(1).to_string()),
+            Some(chain(2).to_string())
+        );
+
+        // With the leaver's teammate still afloat, two sides remain.
+        let mut fourth = room.players[1].clone();
+        fourth.chain_id = chain(4).to_string();
+        room.players.push(fourth);
+        room.players[2].eliminated = false;
+        room.settings.teams = true;
+        assign_teams(&mut room);
+        assert_eq!(forfeit_winner(&room, &chain(1).to_string()), None);
+        room.players[2].eliminated = true;
+        assert_eq!(
+            forfeit_winner(&room, &chain(1).to_string()),
+            Some(chain(2).to_string())
+        );
+    }
+
+    #[test]
+    fn bounced_initial_sync_unseats_the_guest_and_refunds_its_stake() {
+        let mut room = room_with(chain(1), chain(2));
+        room.game_state = GameState::PlacingBoards;
+        room.stakes = vec![Stake {
+            chain_id: chain(2).to_string(),
+            amount: Amount::from_tokens(5),
+        }];
+        let message = CrossChainMessage::InitialStateSync { room: room.clone() };
+        let (host, guest) = (chain(1).to_string(), chain(2).to_string());
+
+        let rollback = roll_back_bounced_message(Some(&mut room), &message, &host, &guest);
+        assert!(rollback.room_changed);
+        assert_eq!(
+            rollback.refund,
+            Some((guest.clone(), Amount::from_tokens(5)))
+        );
+        assert_eq!(room.players.len(), 1);
+        assert!(room.stakes.is_empty());
+        assert_eq!(room.game_state, GameState::WaitingForPlayer);
+
+        // Once the room has moved on, a late bounce changes nothing.
+        let mut started = room_with(chain(1), chain(2));
+        let rollback = roll_back_bounced_message(Some(&mut started), &message, &host, &guest);
+        assert_eq!(rollback, BounceRollback::default());
+        assert_eq!(started.players.len(), 2);
+    }
+
+    #[test]
+    fn bounced_leave_notice_only_notifies_the_leaver() {
+        let message = CrossChainMessage::LeaveNotice {
+            player_chain_id: chain(2),
+        };
+        let (host, guest) = (chain(1).to_string(), chain(2).to_string());
+        let rollback = roll_back_bounced_message(None, &message, &guest, &host);
+        assert!(!rollback.room_changed);
+        assert_eq!(rollback.refund, None);
+        assert_eq!(
+            rollback.notification.as_deref(),
+            Some("Leave notice was rejected")
+        );
+
+        let mut room = room_with(chain(1), chain(2));
+        let rollback = roll_back_bounced_message(Some(&mut room), &message, &guest, &host);
+        assert!(!rollback.room_changed);
+        assert_eq!(room.players.len(), 2);
+    }
+
+    #[test]
+    fn rejects_room_sync_not_sent_by_host() {
+        let room = room_with(chain(1), chain(2));
+        assert!(authenticate_room_host(&room, Some(chain(1))).is_ok());
+        assert!(authenticate_room_host(&room, Some(chain(2))).is_err());
+    }
+
+    #[test]
+    fn rejects_missing_origin() {
+        assert!(authenticate_sender(None, chain(1)).is_err());
+    }
+
+    fn two_ship_board() -> Board {
+        let placements = [
+            ShipPlacementInput {
+                row: 0,
+                col: 0,
+                length: 1,
+                axis: Axis::Horiz,
+            },
+            ShipPlacementInput {
+                row: 0,
+                col: 2,
+                length: 2,
+                axis: Axis::Vert,
+            },
+        ];
+        validate_and_build_board(10, &placements, &[], &[], &RoomSettings::default()).unwrap()
+    }
+
+    #[test]
+    fn turn_rules_decide_extra_shots() {
+        assert!(attacker_keeps_turn(TurnRule::ExtraShotOnHit, true, false));
+        assert!(!attacker_keeps_turn(TurnRule::StrictAlternate, true, true));
+        assert!(!attacker_keeps_turn(TurnRule::ExtraShotOnSink, true, false));
+        assert!(attacker_keeps_turn(TurnRule::ExtraShotOnSink, true, true));
+        assert!(!attacker_keeps_turn(TurnRule::ExtraShotOnHit, false, false));
+    }
+
+    #[test]
+    fn salvo_resolves_every_shot() {
+        let mut board = two_ship_board();
+        let shots = [
+            Coord { row: 0, col: 0 },
+            Coord { row: 0, col: 2 },
+            Coord { row: 5, col: 5 },
+        ];
+        let (results, game_over) = apply_salvo(&mut board, &shots, PlacementRule::NoTouch).unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].hit && results[0].sunk);
+        assert!(results[1].hit && !results[1].sunk);
+        assert!(!results[2].hit);
+        assert!(!game_over);
+    }
+
+    #[test]
+    fn salvo_shot_on_fresh_padding_is_a_miss() {
+        let mut board = two_ship_board();
+        let shots = [Coord { row: 0, col: 0 }, Coord { row: 1, col: 1 }];
+        let (results, _) = apply_salvo(&mut board, &shots, PlacementRule::NoTouch).unwrap();
+        assert!(results[0].sunk);
+        assert!(!results[1].hit);
+    }
+
+    fn diagonal_pair() -> [ShipPlacementInput; 2] {
+        [
+            ShipPlacementInput {
+                row: 0,
+                col: 0,
+                length: 1,
+                axis: Axis::Horiz,
+            },
+            ShipPlacementInput {
+                row: 1,
+                col: 1,
+                length: 1,
+                axis: Axis::Horiz,
+            },
+        ]
+    }
+
+    fn rules(placement_rule: PlacementRule) -> RoomSettings {
+        RoomSettings {
+            placement_rule,
+            ..RoomSettings::default()
+        }
+    }
+
+    #[test]
+    fn placement_rule_controls_touching() {
+        let diagonal = diagonal_pair();
+        assert!(
+            validate_and_build_board(10, &diagonal, &[], &[], &rules(PlacementRule::NoTouch))
+                .is_err()
+        );
+        assert!(validate_and_build_board(
+            10,
+            &diagonal,
+            &[],
+            &[],
+            &rules(PlacementRule::DiagonalTouch)
+        )
+        .is_ok());
+
+        let edge = [
+            ShipPlacementInput {
+                row: 0,
+                col: 0,
+                length: 1,
+                axis: Axis::Horiz,
+            },
+            ShipPlacementInput {
+                row: 0,
+                col: 1,
+                length: 1,
+                axis: Axis::Horiz,
+            },
+        ];
+        assert!(validate_and_build_board(
+            10,
+            &edge,
+            &[],
+            &[],
+            &rules(PlacementRule::DiagonalTouch)
+        )
+        .is_err());
+        assert!(
+            validate_and_build_board(10, &edge, &[], &[], &rules(PlacementRule::AnyTouch)).is_ok()
+        );
+    }
+
+    #[test]
+    fn sunk_padding_only_reveals_guaranteed_water() {
+        let settings = rules(PlacementRule::DiagonalTouch);
+        let mut board =
+            validate_and_build_board(10, &diagonal_pair(), &[], &[], &settings).unwrap();
+        apply_attack(&mut board, 0, 0).unwrap();
+        let (_, adjacent) =
+            apply_sunk_padding(&mut board, 0, PlacementRule::DiagonalTouch).unwrap();
+        assert_eq!(
+            adjacent,
+            vec![Coord { row: 0, col: 1 }, Coord { row: 1, col: 0 }]
+        );
+
+        let settings = rules(PlacementRule::AnyTouch);
+        let mut board =
+            validate_and_build_board(10, &diagonal_pair(), &[], &[], &settings).unwrap();
+        apply_attack(&mut board, 0, 0).unwrap();
+        let (_, adjacent) = apply_sunk_padding(&mut board, 0, PlacementRule::AnyTouch).unwrap();
+        assert!(adjacent.is_empty());
+    }
+
+    #[test]
+    fn orients_l_shape() {
+        let l = builtin_shapes()
+            .into_iter()
+            .find(|s| s.shape_id == "L")
+            .unwrap();
+        assert_eq!(
+            orient_shape(&l.cells, Rotation::R90, false),
+            vec![(0, 0), (0, 1), (0, 2), (1, 0)]
+        );
+        assert_eq!(
+            orient_shape(&l.cells, Rotation::R0, true),
+            vec![(0, 1), (1, 1), (2, 0), (2, 1)]
+        );
+    }
+
+    #[test]
+    fn builds_board_from_shapes() {
+        let settings = RoomSettings {
+            custom_shapes: vec![ShapeDefinition {
+                shape_id: "S".into(),
+                cells: vec![
+                    Coord { row: 0, col: 1 },
+                    Coord { row: 0, col: 2 },
+                    Coord { row: 1, col: 0 },
+                    Coord { row: 1, col: 1 },
+                ],
+            }],
+            ..RoomSettings::default()
+        };
+        assert!(validate_custom_shapes(&settings.custom_shapes).is_ok());
+        let shapes = [
+            ShapePlacementInput {
+                shape_id: "T".into(),
+                row: 0,
+                col: 0,
+                rotation: Rotation::R180,
+                reflected: false,
+            },
+            ShapePlacementInput {
+                shape_id: "S".into(),
+                row: 5,
+                col: 5,
+                rotation: Rotation::R0,
+                reflected: false,
+            },
+        ];
+        let board = validate_and_build_board(10, &[], &shapes, &[], &settings).unwrap();
+        assert_eq!(board.ships.len(), 2);
+        assert_eq!(
+            board.ships[0].cells,
+            vec![
+                Coord { row: 0, col: 1 },
+                Coord { row: 1, col: 0 },
+                Coord { row: 1, col: 1 },
+                Coord { row: 1, col: 2 }
+            ]
+        );
+
+        let touching = [
+            shapes[0].clone(),
+            ShapePlacementInput {
+                shape_id: "O".into(),
+                row: 2,
+                col: 2,
+                rotation: Rotation::R0,
+                reflected: false,
+            },
+        ];
+        assert!(validate_and_build_board(10, &[], &touching, &[], &settings).is_err());
+    }
+
+    #[test]
+    fn rejects_disconnected_custom_shape() {
+        let shapes = [ShapeDefinition {
+            shape_id: "gap".into(),
+            cells: vec![Coord { row: 0, col: 0 }, Coord { row: 0, col: 2 }],
+        }];
+        assert!(validate_custom_shapes(&shapes).is_err());
+    }
+
+    #[test]
+    fn islands_and_mines_change_attacks() {
+        let settings = RoomSettings {
+            islands: vec![Coord { row: 9, col: 9 }],
+            mines_per_player: 1,
+            ..RoomSettings::default()
+        };
+        let ships = [ShipPlacementInput {
+            row: 0,
+            col: 0,
+            length: 2,
+            axis: Axis::Horiz,
+        }];
+        let on_island = [ShipPlacementInput {
+            row: 9,
+            col: 8,
+            length: 2,
+            axis: Axis::Horiz,
+        }];
+        assert!(validate_and_build_board(10, &on_island, &[], &[], &settings).is_err());
+        assert!(
+            validate_and_build_board(10, &ships, &[], &[Coord { row: 1, col: 1 }], &settings)
+                .is_err()
+        );
+
+        let mines = [Coord { row: 5, col: 5 }];
+        let mut board = validate_and_build_board(10, &ships, &[], &mines, &settings).unwrap();
+        assert!(apply_attack(&mut board, 9, 9).is_err());
+        let outcome = apply_attack(&mut board, 5, 5).unwrap();
+        assert!(outcome.mine && !outcome.hit);
+    }
+
+    #[test]
+    fn mine_penalty_skips_next_turn() {
+        let (a, b) = (chain(1).to_string(), chain(2).to_string());
+        let mut room = room_with(chain(1), chain(2));
+        // A hits a mine on B's board: the turn passes to B and A owes a turn.
+        assert_eq!(advance_turn(&mut room, &a, true, true), b);
+        assert_eq!(room.skip_next_turn, vec![a.clone()]);
+        // B misses, but A skips, so B fires again and the penalty is spent.
+        assert_eq!(advance_turn(&mut room, &b, false, false), b);
+        assert!(room.skip_next_turn.is_empty());
+        assert_eq!(advance_turn(&mut room, &b, false, false), a);
+    }
+
+    fn free_for_all() -> Room {
+        let mut room = room_with(chain(1), chain(2));
+        room.settings.max_players = 3;
+        let mut third = room.players[1].clone();
+        third.chain_id = chain(3).to_string();
+        room.players.push(third);
+        room
+    }
+
+    #[test]
+    fn turn_order_skips_eliminated_players() {
+        let mut room = free_for_all();
+        let (a, b, c) = (
+            chain(1).to_string(),
+            chain(2).to_string(),
+            chain(3).to_string(),
+        );
+        assert_eq!(advance_turn(&mut room, &a, false, false), b);
+        eliminate_player(&mut room, &b);
+        assert_eq!(advance_turn(&mut room, &a, false, false), c);
+        assert_eq!(advance_turn(&mut room, &c, false, false), a);
+    }
+
+    #[test]
+    fn target_required_while_several_opponents_remain() {
+        let mut room = free_for_all();
+        let (a, b, c) = (
+            chain(1).to_string(),
+            chain(2).to_string(),
+            chain(3).to_string(),
+        );
+        assert!(resolve_target(&room, &a, None).is_err());
+        assert!(resolve_target(&room, &a, Some(a.as_str())).is_err());
+        assert_eq!(resolve_target(&room, &a, Some(c.as_str())).unwrap(), c);
+        eliminate_player(&mut room, &c);
+        assert!(resolve_target(&room, &a, Some(c.as_str())).is_err());
+        assert_eq!(resolve_target(&room, &a, None).unwrap(), b);
+    }
+
+    #[test]
+    fn last_fleet_standing_wins() {
+        let mut room = free_for_all();
+        record_ship_sunk(&mut room, &chain(2).to_string());
+        record_ship_sunk(&mut room, &chain(2).to_string());
+        assert!(room.players[1].eliminated);
+        assert!(!finish_if_last_standing(&mut room));
+        eliminate_player(&mut room, &chain(1).to_string());
+        assert!(finish_if_last_standing(&mut room));
+        assert_eq!(room.winner_chain_id, Some(chain(3).to_string()));
+    }
+
+    fn weapon(weapon: Weapon, row: u8, col: u8) -> WeaponShot {
+        WeaponShot { weapon, row, col }
+    }
+
+    #[test]
+    fn sonar_counts_ship_cells_without_attacking() {
+        let mut board = two_ship_board();
+        let outcome = apply_weapon(
+            &mut board,
+            weapon(Weapon::Sonar, 1, 1),
+            PlacementRule::NoTouch,
+        )
+        .unwrap();
+        assert_eq!(outcome.sonar_count, Some(3));
+        assert!(outcome.shots.is_empty());
+        assert!(board.cells.iter().all(|c| !c.attacked));
+    }
+
+    #[test]
+    fn cross_bomb_hits_centre_and_edge_neighbours() {
+        let mut board = two_ship_board();
+        let outcome = apply_weapon(
+            &mut board,
+            weapon(Weapon::CrossBomb, 0, 1),
+            PlacementRule::NoTouch,
+        )
+        .unwrap();
+        // Centre (0,1) plus (0,0), (0,2) and (1,1); the top neighbour is off the board.
+        assert_eq!(outcome.shots.len(), 4);
+        assert!(outcome
+            .shots
+            .iter()
+            .any(|s| s.row == 0 && s.col == 0 && s.sunk));
+        assert!(outcome
+            .shots
+            .iter()
+            .any(|s| s.row == 0 && s.col == 2 && s.hit));
+    }
+
+    #[test]
+    fn torpedo_stops_at_first_ship() {
+        let mut board = two_ship_board();
+        let outcome = apply_weapon(
+            &mut board,
+            weapon(Weapon::Torpedo, 1, 0),
+            PlacementRule::NoTouch,
+        )
+        .unwrap();
+        let cols: Vec<u8> = outcome.shots.iter().map(|s| s.col).collect();
+        assert_eq!(cols, vec![0, 1, 2]);
+        assert!(outcome.shots[2].hit);
+        assert!(!board.cells[idx(10, 1, 3)].attacked);
+    }
+
+    #[test]
+    fn weapon_uses_are_limited_per_player() {
+        let mut room = room_with(chain(1), chain(2));
+        let host = chain(1).to_string();
+        assert_eq!(weapons_remaining(&room, &host).torpedo, 1);
+        record_weapon_use(&mut room, &host, Weapon::Torpedo);
+        assert_eq!(weapons_remaining(&room, &host).torpedo, 0);
+        assert_eq!(weapons_remaining(&room, &chain(2).to_string()).torpedo, 1);
+    }
+
+    fn two_vs_two() -> Room {
+        let mut room = free_for_all();
+        room.settings.max_players = 4;
+        room.settings.teams = true;
+        let mut fourth = room.players[1].clone();
+        fourth.chain_id = chain(4).to_string();
+        room.players.push(fourth);
+        assign_teams(&mut room);
+        room
+    }
+
+    #[test]
+    fn teams_alternate_turns_and_cannot_target_teammates() {
+        let mut room = two_vs_two();
+        let (a, b, c, d) = (
+            chain(1).to_string(),
+            chain(2).to_string(),
+            chain(3).to_string(),
+            chain(4).to_string(),
+        );
+        assert_eq!(teammate_of(&room, &a), Some(c.clone()));
+        assert!(resolve_target(&room, &a, Some(c.as_str())).is_err());
+        assert!(resolve_target(&room, &a, Some(d.as_str())).is_ok());
+        // With B out, team 1 is down to D, who gets every other turn.
+        eliminate_player(&mut room, &b);
+        assert_eq!(advance_turn(&mut room, &a, false, false), d);
+        assert_eq!(advance_turn(&mut room, &d, false, false), a);
+        assert_eq!(advance_turn(&mut room, &c, false, false), d);
+    }
+
+    #[test]
+    fn team_loses_only_when_both_fleets_are_sunk() {
+        let mut room = two_vs_two();
+        eliminate_player(&mut room, &chain(2).to_string());
+        assert!(!finish_if_last_standing(&mut room));
+        eliminate_player(&mut room, &chain(1).to_string());
+        assert!(!finish_if_last_standing(&mut room));
+        eliminate_player(&mut room, &chain(4).to_string());
+        assert!(finish_if_last_standing(&mut room));
+        assert_eq!(room.winner_chain_id, Some(chain(3).to_string()));
+    }
+
+    #[test]
+    fn salvo_rejects_duplicates_without_touching_board() {
+        let mut board = two_ship_board();
+        let shots = [Coord { row: 0, col: 0 }, Coord { row: 0, col: 0 }];
+        assert!(apply_salvo(&mut board, &shots, PlacementRule::NoTouch).is_err());
+        assert!(board.cells.iter().all(|c| !c.attacked));
+    }
+
+    #[test]
+    fn generated_board_depends_only_on_the_seed() {
+        let seed = chain_seed(&chain(1).to_string(), 7);
+        let first = generate_random_board(10, &STANDARD_FLEET, seed).unwrap();
+        let again = generate_random_board(10, &STANDARD_FLEET, seed).unwrap();
+        let cells =
+            |p: &[ShipPlacementInput]| p.iter().map(|s| (s.row, s.col, s.axis)).collect::<Vec<_>>();
+        assert_eq!(cells(&first), cells(&again));
+        assert_ne!(seed, chain_seed(&chain(1).to_string(), 8));
+        assert_ne!(seed, chain_seed(&chain(2).to_string(), 7));
+        assert!(generate_random_board(10, &[11], seed).is_err());
+    }
+
+    #[test]
+    fn heatmap_counts_placements_and_favours_hits() {
+        let mut view = empty_enemy_view("enemy", 3, &[]);
+        let weights = target_heatmap(&view, &[2], PlacementRule::NoTouch);
+        // Corners fit 2 placements of a 2-ship, edges 3 and the centre 4.
+        assert_eq!(weights, vec![2, 3, 2, 3, 4, 3, 2, 3, 2]);
+
+        view.cells[4] = EnemyCell::Miss;
+        view.cells[0] = EnemyCell::Hit;
+        let weights = target_heatmap(&view, &[2], PlacementRule::NoTouch);
+        assert_eq!(weights[0], 0);
+        assert_eq!(weights[4], 0);
+        assert!(weights[1] > weights[2]);
+        assert_eq!(weights[1], 2 + HIT_WEIGHT);
+
+        view.cells[0] = EnemyCell::Sunk;
+        let weights = target_heatmap(&view, &[2], PlacementRule::NoTouch);
+        assert_eq!((weights[1], weights[3]), (0, 0));
+    }
+
+    #[test]
+    fn fleet_rule_is_enforced_and_tracked() {
+        let settings = RoomSettings {
+            fleet: vec![2, 1],
+            ..RoomSettings::default()
+        };
+        let placements = random_board_placements(10, &[1, 2], &settings, 3).unwrap();
+        let mut board = validate_and_build_board(10, &placements, &[], &[], &settings).unwrap();
+        assert!(validate_and_build_board(10, &placements[..1], &[], &[], &settings).is_err());
+
+        let one = board
+            .ships
+            .iter()
+            .find(|s| s.cells.len() == 1)
+            .unwrap()
+            .cells[0];
+        let outcome = apply_attack(&mut board, one.row, one.col).unwrap();
+        let ship_id = outcome.ship_id.unwrap();
+        let (cells, adjacent) =
+            apply_sunk_padding(&mut board, ship_id, settings.placement_rule).unwrap();
+        let status = fleet_status(&board);
+        assert_eq!(status.iter().filter(|s| s.sunk).count(), 1);
+        assert!(status.iter().any(|s| s.size == 2 && s.hits == 0 && !s.sunk));
+
+        let mut view = empty_enemy_view("enemy", 10, &[]);
+        record_shot(
+            &mut view,
+            &ShotResult {
+                row: one.row,
+                col: one.col,
+                hit: true,
+                sunk: true,
+                mine: false,
+                sunk_ship_cells: Some(cells),
+                adjacent_coords: Some(adjacent),
+            },
+        );
+        let enemy = enemy_fleet_status(&view, &settings);
+        assert_eq!(enemy.sunk, vec![1]);
+        assert_eq!(enemy.remaining, Some(vec![2]));
+        assert_eq!(
+            enemy_fleet_status(&view, &RoomSettings::default()).remaining,
+            None
+        );
+    }
+
+    #[test]
+    fn saved_layouts_are_validated_and_limited() {
+        let mut layouts = Vec::new();
+        let layout = |name: &str, seed: u64| SavedLayout {
+            name: name.to_string(),
+            ships: generate_random_board(10, &STANDARD_FLEET, seed).unwrap(),
+        };
+        for i in 0..MAX_SAVED_LAYOUTS {
+            save_layout(&mut layouts, layout(&format!("layout {}", i), i as u64)).unwrap();
+        }
+        assert!(save_layout(&mut layouts, layout("one too many", 0)).is_err());
+        save_layout(&mut layouts, layout("layout 0", 99)).unwrap();
+        assert_eq!(layouts.len(), MAX_SAVED_LAYOUTS);
+
+        let overlapping = SavedLayout {
+            name: "bad".to_string(),
+            ships: diagonal_pair().into_iter().chain(diagonal_pair()).collect(),
+        };
+        layouts.clear();
+        assert!(save_layout(&mut layouts, overlapping).is_err());
+        assert!(save_layout(&mut layouts, layout("", 1)).is_err());
+        assert!(layouts.is_empty());
+    }
+
+    #[test]
+    fn join_code_is_checked_against_salted_hash() {
+        let hash = hash_join_code("room-1", "sesame");
+        assert_ne!(hash, hash_join_code("room-2", "sesame"));
+        assert!(check_join_code("room-1", None, None).is_ok());
+        assert!(check_join_code("room-1", Some(&hash), Some("sesame")).is_ok());
+        assert!(check_join_code("room-1", Some(&hash), Some("open")).is_err());
+        assert!(check_join_code("room-1", Some(&hash), None).is_err());
+    }
+
+    #[test]
+    fn lobby_lists_open_rooms_and_filters_them() {
+        let mut room = room_with(chain(1), chain(2));
+        room.game_state = GameState::WaitingForPlayer;
+        room.players.truncate(1);
+        room.settings.max_players = 3;
+        assert!(is_open_for_listing(&room));
+        let listing = room_listing(&room, "Friday Salvo", "0".into());
+        assert_eq!((listing.host_name.as_str(), listing.players), ("host", 1));
+
+        let filter = |f: LobbyFilter| listing_matches(&listing, &f);
+        assert!(filter(LobbyFilter::default()));
+        assert!(filter(LobbyFilter {
+            name_contains: Some("salvo".into()),
+            max_players: Some(3),
+            ..LobbyFilter::default()
+        }));
+        assert!(!filter(LobbyFilter {
+            game_mode: Some(GameMode::Salvo),
+            ..LobbyFilter::default()
+        }));
+
+        room.players = free_for_all().players;
+        assert!(!is_open_for_listing(&room));
+    }
+
+    #[test]
+    fn matchmaking_pairs_only_compatible_preferences() {
+        let classic = MatchPreferences::default();
+        let strict = MatchPreferences {
+            turn_rule: Some(TurnRule::StrictAlternate),
+            ..MatchPreferences::default()
+        };
+        let ranked = MatchPreferences {
+            ranked: true,
+            fleet: STANDARD_FLEET.to_vec(),
+            ..MatchPreferences::default()
+        };
+        assert!(preferences_compatible(&classic, &strict));
+        assert!(!preferences_compatible(&classic, &ranked));
+        assert!(!preferences_compatible(
+            &strict,
+            &MatchPreferences {
+                turn_rule: Some(TurnRule::ExtraShotOnSink),
+                ..MatchPreferences::default()
+            }
+        ));
+
+        let settings = match_settings(&classic, &strict);
+        assert_eq!(settings.turn_rule, TurnRule::StrictAlternate);
+        let settings = match_settings(&ranked, &ranked);
+        assert!(settings.ranked && !settings.target_hints);
+        assert_eq!(settings.fleet, STANDARD_FLEET.to_vec());
+        assert!(validate_preferences(&MatchPreferences {
+            board_size: 12,
+            ..MatchPreferences::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn queue_stats_group_by_mode_and_average_recent_waits() {
+        let searching = |chain_id: u8, game_mode: GameMode, ranked: bool| MatchmakingPlayer {
+            chain_id: chain(chain_id).to_string(),
+            player_name: "p".into(),
+            preferences: MatchPreferences {
+                game_mode,
+                ranked,
+                ..MatchPreferences::default()
+            },
+            enqueued_at: 0,
+            hops: 0,
+        };
+        let queue = [
+            searching(1, GameMode::Classic, false),
+            searching(2, GameMode::Salvo, true),
+            searching(3, GameMode::Classic, false),
+        ];
+        let mut waits = Vec::new();
+        assert_eq!(queue_stats(&queue, &waits).average_wait_secs, None);
+        for _ in 0..RECENT_MATCH_WAITS {
+            record_match_wait(&mut waits, 60_000_000);
+        }
+        record_match_wait(&mut waits, 80_000_000 + 60_000_000);
+        assert_eq!(waits.len(), RECENT_MATCH_WAITS);
+
+        let stats = queue_stats(&queue, &waits);
+        assert_eq!(stats.size, 3);
+        assert_eq!(stats.average_wait_secs, Some(64));
+        assert_eq!(stats.by_mode.len(), 2);
+        assert_eq!(stats.by_mode[0].players, 2);
+    }
+
+    #[test]
+    fn only_official_orchestrators_are_trusted() {
+        let open = BattleshipParameters::default();
+        assert!(authenticate_orchestrator(&open, Some(chain(9))).is_ok());
+        assert!(open.choose_orchestrator(None).is_err());
+        assert_eq!(
+            open.choose_orchestrator(Some(&chain(9).to_string())),
+            Ok(chain(9))
+        );
+
+        let official = BattleshipParameters {
+            orchestrator_chain_ids: vec![chain(1), chain(2)],
+            ..BattleshipParameters::default()
+        };
+        assert!(authenticate_orchestrator(&official, Some(chain(2))).is_ok());
+        assert!(authenticate_orchestrator(&official, Some(chain(9))).is_err());
+        assert!(authenticate_orchestrator(&official, None).is_err());
+        assert_eq!(official.choose_orchestrator(None), Ok(chain(1)));
+        assert!(official
+            .choose_orchestrator(Some(&chain(9).to_string()))
+            .is_err());
+        assert!(!official.is_orchestrator(chain(9)));
+    }
+
+    #[test]
+    fn players_pick_shards_by_region_or_mode_and_stale_ones_move_on() {
+        let shard = |n: u8, region: Option<&str>| MatchmakingShard {
+            chain_id: chain(n),
+            region: region.map(String::from),
+        };
+        let parameters = BattleshipParameters {
+            matchmaking_shards: vec![shard(1, Some("eu")), shard(2, None), shard(3, None)],
+            ..BattleshipParameters::default()
+        };
+        assert_eq!(
+            parameters.shard_for(Some("eu"), GameMode::Salvo),
+            Some(chain(1))
+        );
+        let classic = parameters.shard_for(None, GameMode::Classic).unwrap();
+        assert!(classic == chain(2) || classic == chain(3));
+        assert_eq!(
+            parameters.shard_for(Some("us"), GameMode::Classic),
+            Some(classic)
+        );
+        assert_eq!(parameters.sibling_shard(chain(3)), Some(chain(1)));
+        assert_eq!(parameters.sibling_shard(chain(9)), None);
+        assert!(parameters.is_orchestrator(chain(2)) && !parameters.is_orchestrator(chain(9)));
+        assert_eq!(parameters.choose_orchestrator(None), Ok(chain(1)));
+        assert_eq!(
+            BattleshipParameters::default().shard_for(None, GameMode::Classic),
+            None
+        );
+
+        let waiting = |n: u8, enqueued_at: u64, hops: u8| MatchmakingPlayer {
+            chain_id: chain(n).to_string(),
+            player_name: "p".into(),
+            preferences: MatchPreferences::default(),
+            enqueued_at,
+            hops,
+        };
+        let now = 200_000_000;
+        let mut queue = vec![
+            waiting(4, 150_000_000, 0),
+            waiting(5, 0, 0),
+            waiting(6, 0, 2),
+            waiting(7, 0, 1),
+        ];
+        let (first, stale) = take_stale_waiters(&mut queue, now, 120, 3);
+        assert_eq!(first, 1);
+        assert_eq!(stale.len(), 2);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(take_stale_waiters(&mut queue, now, 0, 3).1.len(), 0);
+    }
+
+    fn tournament_with(format: TournamentFormat, players: u8) -> Tournament {
+        let mut tournament = new_tournament(
+            "t".into(),
+            "Cup".into(),
+            format,
+            RoomSettings::default(),
+            8,
+            None,
+        )
+        .unwrap();
+        for n in 1..=players {
+            register_tournament_player(&mut tournament, &chain(n).to_string(), format!("p{}", n))
+                .unwrap();
+        }
+        tournament
+    }
+
+    fn play_round(
+        tournament: &mut Tournament,
+        matches: &[TournamentMatch],
+    ) -> Vec<TournamentMatch> {
+        let mut next = Vec::new();
+        for game in matches {
+            next = record_tournament_result(tournament, &game.host_chain_id, &game.host_chain_id)
+                .unwrap();
+        }
+        next
+    }
+
+    #[test]
+    fn single_elimination_gives_each_seed_at_most_one_bye_and_crowns_one_champion() {
+        let mut tournament = tournament_with(TournamentFormat::SingleElimination, 5);
+        assert!(
+            register_tournament_player(&mut tournament, &chain(1).to_string(), "again".into())
+                .is_err()
+        );
+        let round_one = start_tournament(&mut tournament).unwrap();
+        assert_eq!(round_one.len(), 2);
+        assert_eq!(tournament.matches[0].guest_chain_id, None);
+        assert_eq!(
+            tournament.matches[0].winner_chain_id,
+            Some(chain(1).to_string())
+        );
+        let outsider = chain(9).to_string();
+        assert!(
+            record_tournament_result(&mut tournament, &round_one[0].host_chain_id, &outsider)
+                .is_err()
+        );
+
+        let round_two = play_round(&mut tournament, &round_one);
+        assert_eq!(round_two.len(), 1);
+        assert_eq!(tournament.round, 2);
+        // Three survivors again: the bye moves on to the next seed.
+        let bye = tournament
+            .matches
+            .iter()
+            .find(|m| m.round == 2 && m.guest_chain_id.is_none())
+            .unwrap();
+        assert_eq!(bye.host_chain_id, chain(2).to_string());
+        assert_eq!(round_two[0].host_chain_id, chain(1).to_string());
+        let round_three = play_round(&mut tournament, &round_two);
+        assert_eq!(round_three.len(), 1);
+        assert!(play_round(&mut tournament, &round_three).is_empty());
+        assert_eq!(tournament.status, TournamentStatus::Finished);
+        assert_eq!(tournament.champion_chain_id, Some(chain(1).to_string()));
+        assert!(register_tournament_player(&mut tournament, &outsider, "late".into()).is_err());
+    }
+
+    #[test]
+    fn swiss_avoids_rematches_and_ends_after_its_rounds() {
+        let mut tournament = tournament_with(TournamentFormat::Swiss, 4);
+        let round_one = start_tournament(&mut tournament).unwrap();
+        assert_eq!(tournament.rounds, 2);
+        let round_two = play_round(&mut tournament, &round_one);
+        assert_eq!(round_two.len(), 2);
+        for game in &round_two {
+            let host = tournament
+                .players
+                .iter()
+                .find(|p| p.chain_id == game.host_chain_id)
+                .unwrap();
+            assert_eq!(host.opponents.len(), 2);
+            assert_ne!(host.opponents[0], host.opponents[1]);
+        }
+        assert!(play_round(&mut tournament, &round_two).is_empty());
+        assert_eq!(tournament.status, TournamentStatus::Finished);
+        let standings = tournament_standings(&tournament);
+        assert_eq!(standings[0].wins, 2);
+        assert_eq!(
+            tournament.champion_chain_id,
+            Some(standings[0].chain_id.clone())
+        );
+    }
+
+    #[test]
+    fn best_of_three_resets_between_games_until_a_side_wins_twice() {
+        let mut room = room_with(chain(1), chain(2));
+        room.settings.best_of = 3;
+        let (host, guest) = (chain(1).to_string(), chain(2).to_string());
+
+        end_game(&mut room, Some(host.clone()));
+        assert_eq!(room.status, RoomStatus::Active);
+        assert_eq!(room.game_state, GameState::Ended);
+        next_series_game(&mut room);
+        assert_eq!(room.game_number, 2);
+        assert_eq!(room.game_state, GameState::PlacingBoards);
+        assert!(room
+            .players
+            .iter()
+            .all(|p| !p.board_submitted && !p.eliminated));
+        assert_eq!(series_first_attacker(&room), Some(guest.clone()));
+
+        end_game(&mut room, Some(guest.clone()));
+        next_series_game(&mut room);
+        assert_eq!(series_first_attacker(&room), Some(host.clone()));
+        assert!(series_leader(&room).is_none());
+        end_game(&mut room, Some(guest.clone()));
+        assert_eq!(room.status, RoomStatus::Ended);
+        assert_eq!(room.winner_chain_id, Some(guest));
+        assert_eq!(room.players[0].series_wins, 1);
+
+        let mut single = room_with(chain(1), chain(2));
+        end_game(&mut single, Some(host.clone()));
+        assert_eq!(single.status, RoomStatus::Ended);
+        assert_eq!(single.players[0].series_wins, 0);
+
+        let settings = RoomSettings {
+            best_of: 4,
+            ..RoomSettings::default()
+        };
+        assert!(validate_room_settings(10, &settings).is_err());
+    }
+
+    #[test]
+    fn winner_takes_the_pot_and_rooms_without_winner_refund() {
+        let mut room = room_with(chain(1), chain(2));
+        room.settings.stake = Amount::from_tokens(5);
+        let (host, guest) = (chain(1).to_string(), chain(2).to_string());
+        for chain_id in [&host, &guest] {
+            room.stakes.push(Stake {
+                chain_id: chain_id.clone(),
+                amount: Amount::from_tokens(5),
+            });
+        }
+        assert_eq!(
+            stake_payouts(&room),
+            Ok(vec![
+                (host.clone(), Amount::from_tokens(5)),
+                (guest.clone(), Amount::from_tokens(5))
+            ])
+        );
+        room.winner_chain_id = Some(guest.clone());
+        assert_eq!(
+            stake_payouts(&room),
+            Ok(vec![(guest.clone(), Amount::from_tokens(10))])
+        );
+
+        // A pot that cannot be summed is never shrunk: it is rejected up front and refunded if it happens.
+        let mut huge = room.clone();
+        huge.settings.stake = Amount::MAX;
+        huge.stakes[0].amount = Amount::MAX;
+        huge.players.pop();
+        huge.game_state = GameState::PlacingBoards;
+        assert_eq!(
+            join_rejection(Some(&huge), &host, Amount::MAX),
+            Some("Stake pot overflows".to_string())
+        );
+        huge.stakes[1].amount = Amount::MAX;
+        huge.winner_chain_id = Some(host.clone());
+        assert!(stake_payouts(&huge).is_err());
+        assert_eq!(take_stake(&mut room, &host), Some(Amount::from_tokens(5)));
+        assert_eq!(take_stake(&mut room, &host), None);
+
+        room.game_state = GameState::PlacingBoards;
+        assert!(!room_started(&room));
+        // Between the games of a series, leaving is still a forfeit.
+        room.game_number = 2;
+        assert!(room_started(&room));
+        room.game_number = 1;
+        room.players.pop();
+        assert_eq!(
+            join_rejection(Some(&room), &host, Amount::ZERO),
+            Some("Room stake is 5.".to_string())
+        );
+        assert_eq!(
+            join_rejection(Some(&room), &host, Amount::from_tokens(5)),
+            None
+        );
+        assert!(join_rejection(Some(&room), &guest, Amount::from_tokens(5)).is_some());
+    }
+
+    #[test]
+    fn host_times_out_a_stalled_attacker() {
+        let mut room = room_with(chain(1), chain(2));
+        let (host, guest) = (chain(1).to_string(), chain(2).to_string());
+        room.current_attacker = Some(guest.clone());
+        room.last_activity_at = 1_000_000;
+        assert!(claim_timeout(&mut room, &host, 100_000_000).is_err());
+        room.settings.turn_seconds = Some(30);
+        assert!(claim_timeout(&mut room, &host, 20_000_000).is_err());
+        assert!(claim_timeout(&mut room, &guest, 40_000_000).is_err());
+        assert!(claim_timeout(&mut room, &chain(3).to_string(), 40_000_000).is_err());
+        assert_eq!(claim_timeout(&mut room, &host, 40_000_000), Ok(guest));
+        assert_eq!(room.status, RoomStatus::Ended);
+        assert_eq!(room.winner_chain_id, Some(host));
+    }
+
+    #[test]
+    fn guest_times_out_a_host_that_does_not_answer() {
+        let (host, guest) = (chain(1).to_string(), chain(2).to_string());
+        let mut guest_copy = room_with(chain(1), chain(2));
+        guest_copy.settings.turn_seconds = Some(30);
+        guest_copy.current_attacker = Some(guest.clone());
+        guest_copy.pending_attack = Some(Coord { row: 0, col: 0 });
+        guest_copy.pending_target = Some(host.clone());
+        guest_copy.last_activity_at = 1_000_000;
+        // The host's copy has no pending attack: it never processed the guest's shot.
+        let mut host_copy = guest_copy.clone();
+        host_copy.pending_attack = None;
+        host_copy.pending_target = None;
+
+        assert!(claim_timeout(&mut guest_copy, &guest, 20_000_000).is_err());
+        assert_eq!(
+            claim_timeout(&mut guest_copy, &guest, 40_000_000),
+            Ok(host.clone())
+        );
+        assert_eq!(guest_copy.pending_attack, None);
+        assert_eq!(guest_copy.winner_chain_id, Some(guest.clone()));
+
+        // The claim reaches the host once its chain runs again, and its copy agrees.
+        assert_eq!(accept_timeout_claim(&mut host_copy, &guest, &host), Ok(()));
+        assert_eq!(host_copy.status, RoomStatus::Ended);
+        assert_eq!(host_copy.winner_chain_id, Some(guest));
+    }
+
+    #[test]
+    fn timeout_claims_must_match_the_turn_state() {
+        let mut room = room_with(chain(1), chain(2));
+        let mut third = room.players[1].clone();
+        third.chain_id = chain(3).to_string();
+        room.players.push(third);
+        let (host, guest, other) = (
+            chain(1).to_string(),
+            chain(2).to_string(),
+            chain(3).to_string(),
+        );
+        assert!(accept_timeout_claim(&mut room.clone(), &guest, &host).is_err());
+        room.settings.turn_seconds = Some(30);
+        assert!(accept_timeout_claim(&mut room.clone(), &other, &guest).is_err());
+        assert!(accept_timeout_claim(&mut room.clone(), &guest, &guest).is_err());
+        assert_eq!(accept_timeout_claim(&mut room, &other, &host), Ok(()));
+        assert!(room.players[0].eliminated);
+        assert_eq!(room.current_attacker, Some(guest));
+        assert_eq!(room.status, RoomStatus::Active);
+    }
+
+    #[test]
+    fn draws_and_aborts_need_every_player_and_have_no_winner() {
+        let mut room = room_with(chain(1), chain(2));
+        let (host, guest) = (chain(1).to_string(), chain(2).to_string());
+        assert!(agree_to_end(&mut room, Proposal::Draw, &guest, true).is_err());
+        assert_eq!(
+            agree_to_end(&mut room, Proposal::Draw, &host, false),
+            Ok(false)
+        );
+        assert!(agree_to_end(&mut room, Proposal::Draw, &host, false).is_err());
+        assert!(agree_to_end(&mut room, Proposal::Draw, &chain(3).to_string(), true).is_err());
+        assert_eq!(
+            agree_to_end(&mut room, Proposal::Draw, &guest, true),
+            Ok(true)
+        );
+        assert_eq!(room.status, RoomStatus::Ended);
+        assert_eq!(room.winner_chain_id, None);
+
+        let mut series = room_with(chain(1), chain(2));
+        series.settings.best_of = 3;
+        agree_to_end(&mut series, Proposal::Draw, &host, false).unwrap();
+        agree_to_end(&mut series, Proposal::Draw, &guest, true).unwrap();
+        assert_eq!(series.status, RoomStatus::Active);
+        assert!(series.players.iter().all(|p| p.series_wins == 0));
+        next_series_game(&mut series);
+        assert!(series.draw_votes.is_empty());
+        agree_to_end(&mut series, Proposal::Abort, &guest, false).unwrap();
+        assert_eq!(
+            agree_to_end(&mut series, Proposal::Abort, &host, true),
+            Ok(true)
+        );
+        assert_eq!(series.status, RoomStatus::Aborted);
+        assert_eq!(series.winner_chain_id, None);
+    }
+
+    #[test]
+    fn random_board_is_a_legal_standard_fleet() {
+        let settings = RoomSettings {
+            islands: vec![Coord { row: 4, col: 4 }, Coord { row: 5, col: 5 }],
+            ..RoomSettings::default()
+        };
+        for seed in 0..20 {
+            let placements = random_board_placements(10, &STANDARD_FLEET, &settings, seed).unwrap();
+            let board = validate_and_build_board(10, &placements, &[], &[], &settings).unwrap();
+            assert_eq!(board.ships.len(), STANDARD_FLEET.len());
+        }
+    }
+
+    #[test]
+    fn bot_finishes_wounded_ships_before_hunting() {
+        let mut view = empty_enemy_view("enemy", 10, &[]);
+        view.cells[3 * 10 + 3] = EnemyCell::Hit;
+        let around = [(2, 3), (4, 3), (3, 2), (3, 4)];
+        for seed in 0..8 {
+            let shot = choose_bot_shot(&view, seed).unwrap();
+            assert!(around.contains(&(shot.row, shot.col)));
+        }
+        // A second hit fixes the axis, so only the two ends of the line remain.
+        view.cells[3 * 10 + 4] = EnemyCell::Hit;
+        for seed in 0..8 {
+            let shot = choose_bot_shot(&view, seed).unwrap();
+            assert!([(3, 2), (3, 5)].contains(&(shot.row, shot.col)));
+        }
+        view.cells[3 * 10 + 3] = EnemyCell::Sunk;
+        view.cells[3 * 10 + 4] = EnemyCell::Sunk;
+        let shot = choose_bot_shot(&view, 7).unwrap();
+        assert_eq!((shot.row + shot.col) % 2, 0);
+    }
+
+    #[test]
+    fn bot_salvo_fires_distinct_unknown_cells() {
+        let mut view = empty_enemy_view("enemy", 10, &[]);
+        view.cells[3 * 10 + 3] = EnemyCell::Hit;
+        let shots = choose_bot_salvo(&view, 5, 3);
+        assert_eq!(shots.len(), 5);
+        for (i, shot) in shots.iter().enumerate() {
+            assert!(!shots[..i].contains(shot));
+            assert_eq!(
+                view.cells[shot.row as usize * 10 + shot.col as usize],
+                EnemyCell::Unknown
+            );
+        }
+        // The shots around the lone hit come first.
+        assert!(shots[..4]
+            .iter()
+            .all(|s| [(2, 3), (4, 3), (3, 2), (3, 4)].contains(&(s.row, s.col))));
+    }
+
+    #[test]
+    fn bot_gets_the_turn_back_after_a_weapon_attack() {
+        let mut room = room_with(chain(1), chain(2));
+        let (player, bot) = (chain(1).to_string(), chain(2).to_string());
+        assert!(!bot_can_fire(&room, &bot));
+
+        // What the bot's `WeaponAttackRequest` handler does once the weapon missed everything.
+        let miss = ShotResult {
+            row: 0,
+            col: 0,
+            hit: false,
+            sunk: false,
+            mine: false,
+            sunk_ship_cells: None,
+            adjacent_coords: None,
+        };
+        let keeps_turn = weapon_keeps_turn(&room.settings, &[miss]);
+        let next = advance_turn(&mut room, &player, keeps_turn, false);
+        room.current_attacker = Some(next);
+        assert!(bot_can_fire(&room, &bot));
+
+        room.pending_weapon = Some(WeaponShot {
+            weapon: Weapon::Sonar,
+            row: 0,
+            col: 0,
+        });
+        assert!(!bot_can_fire(&room, &bot));
+    }
+}
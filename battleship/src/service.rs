@@ -6,10 +6,20 @@ use std::sync::Arc;
 
 use async_graphql::{EmptySubscription, Object, Request, Response, Schema};
 use battleship_game::{
-    BattleshipAbi, Board, EnemyBoardView, GameState, MyBoardView, MyCellView, Operation, Room, RoomStatus,
-    Invitation, RevealInfo, ShipPlacementInput, ShipView,
+    chain_seed, enemy_fleet_status, fleet_remaining, fleet_status, generate_random_board,
+    listing_matches, queue_stats, random_board_placements, target_heatmap, tournament_standings,
+    BattleshipAbi, BattleshipParameters, Board, Coord, EnemyBoardView, EnemyFleetStatus, GameMode,
+    GameState, Invitation, LobbyFilter, MatchPreferences, MatchmakingPlayer, MatchmakingQueueStats,
+    MyBoardView, MyCellView, Operation, PlacementRule, QueueStatus, RevealInfo, Room, RoomListing,
+    RoomSettings, RoomStatus, SavedLayout, ShapeDefinition, ShapePlacementInput,
+    ShipPlacementInput, ShipStatus, ShipView, TargetHeatmap, Tournament, TournamentFormat,
+    TournamentMatchRef, TournamentPlayer, Weapon, WeaponLoadout, BOARD_SIZE, STANDARD_FLEET,
+};
+use linera_sdk::{
+    linera_base_types::{Amount, WithServiceAbi},
+    views::View,
+    Service, ServiceRuntime,
 };
-use linera_sdk::{linera_base_types::WithServiceAbi, views::View, Service, ServiceRuntime};
 
 use self::state::BattleshipState;
 
@@ -25,7 +35,7 @@ impl WithServiceAbi for BattleshipService {
 }
 
 impl Service for BattleshipService {
-    type Parameters = ();
+    type Parameters = BattleshipParameters;
 
     async fn new(runtime: ServiceRuntime<Self>) -> Self {
         let state = BattleshipState::load(runtime.root_view_storage_context())
@@ -39,8 +49,9 @@ impl Service for BattleshipService {
 
     async fn handle_query(&self, request: Request) -> Response {
         let room = self.state.room.get().clone();
-        let enemy_view = self.state.enemy_view.get().clone();
+        let enemy_views = self.state.enemy_views.get().clone();
         let board = self.state.board.get().clone();
+        let teammate_board = self.state.teammate_board.get().clone();
         let has_board = board.is_some();
         let last_reveal = self.state.last_reveal.get().clone();
         let last_notification = self.state.last_notification.get().clone();
@@ -48,19 +59,41 @@ impl Service for BattleshipService {
         let friend_requests_received = self.state.friend_requests_received.get().clone();
         let friend_requests_sent = self.state.friend_requests_sent.get().clone();
         let room_invitations = self.state.room_invitations.get().clone();
+        let bot_name = self.state.bot_name.get().clone();
+        let saved_layouts = self.state.saved_layouts.get().clone();
+        let open_rooms = self.state.open_rooms.get().clone();
+        let matchmaking_queue = self.state.matchmaking_queue.get().clone();
+        let recent_match_waits = self.state.recent_match_waits.get().clone();
+        let matchmaking_status = self.state.matchmaking_status.get().clone();
+        let tournament = self.state.tournament.get().clone();
+        let tournament_organizer = self.state.tournament_organizer.get().clone();
+        let tournament_match = self.state.tournament_match.get().clone();
+        let parameters = self.runtime.application_parameters();
         let schema = Schema::build(
             QueryRoot {
                 room,
-                enemy_view,
+                enemy_views,
                 board,
+                teammate_board,
                 has_board,
                 chain_id: self.runtime.chain_id().to_string(),
+                block_height: self.runtime.next_block_height().0,
                 last_reveal,
                 last_notification,
                 friends,
                 friend_requests_received,
                 friend_requests_sent,
                 room_invitations,
+                bot_name,
+                saved_layouts,
+                open_rooms,
+                matchmaking_queue,
+                recent_match_waits,
+                matchmaking_status,
+                tournament,
+                tournament_organizer,
+                tournament_match,
+                parameters,
             },
             MutationRoot {
                 runtime: self.runtime.clone(),
@@ -74,16 +107,28 @@ impl Service for BattleshipService {
 
 struct QueryRoot {
     room: Option<Room>,
-    enemy_view: Option<EnemyBoardView>,
+    enemy_views: Vec<EnemyBoardView>,
     board: Option<Board>,
+    teammate_board: Option<Board>,
     has_board: bool,
     chain_id: String,
+    block_height: u64,
     last_reveal: Option<RevealInfo>,
     last_notification: Option<String>,
     friends: Vec<String>,
     friend_requests_received: Vec<String>,
     friend_requests_sent: Vec<String>,
     room_invitations: Vec<Invitation>,
+    bot_name: Option<String>,
+    saved_layouts: Vec<SavedLayout>,
+    open_rooms: Vec<RoomListing>,
+    matchmaking_queue: Vec<MatchmakingPlayer>,
+    recent_match_waits: Vec<u64>,
+    matchmaking_status: Option<QueueStatus>,
+    tournament: Option<Tournament>,
+    tournament_organizer: Option<String>,
+    tournament_match: Option<TournamentMatchRef>,
+    parameters: BattleshipParameters,
 }
 
 #[Object]
@@ -108,8 +153,19 @@ impl QueryRoot {
             .unwrap_or(false)
     }
 
-    async fn enemy_view(&self) -> Option<&EnemyBoardView> {
-        self.enemy_view.as_ref()
+    /// What is known about one opponent's board; defaults to the first opponent.
+    async fn enemy_view(&self, chain_id: Option<String>) -> Option<&EnemyBoardView> {
+        match chain_id {
+            Some(chain_id) => self
+                .enemy_views
+                .iter()
+                .find(|v| v.owner_chain_id == chain_id),
+            None => self.enemy_views.first(),
+        }
+    }
+
+    async fn enemy_views(&self) -> &Vec<EnemyBoardView> {
+        &self.enemy_views
     }
 
     async fn has_submitted_board(&self) -> bool {
@@ -117,33 +173,102 @@ impl QueryRoot {
     }
 
     async fn my_board(&self) -> Option<MyBoardView> {
-        let board = self.board.as_ref()?;
-        let size = board.size;
-        let mut cells = Vec::with_capacity(board.cells.len());
-        for row in 0..size {
-            for col in 0..size {
-                let idx = (row as usize) * (size as usize) + (col as usize);
-                if let Some(cell) = board.cells.get(idx) {
-                    cells.push(MyCellView {
-                        row,
-                        col,
-                        ship_id: cell.ship_id,
-                        attacked: cell.attacked,
-                    });
-                }
-            }
+        self.board.as_ref().map(board_view)
+    }
+
+    /// Own board of the teammate in team rooms, shared at game start and after every attack on it.
+    async fn teammate_board(&self) -> Option<MyBoardView> {
+        self.teammate_board.as_ref().map(board_view)
+    }
+
+    async fn shape_catalogue(&self) -> Vec<ShapeDefinition> {
+        let custom_shapes = self
+            .room
+            .as_ref()
+            .map(|r| r.settings.custom_shapes.as_slice())
+            .unwrap_or_default();
+        battleship_game::shape_catalogue(custom_shapes)
+    }
+
+    /// A random legal layout under the current room's rules, seeded from this chain's id and next block height
+    /// unless a `seed` is given. Defaults to the room's fleet, or the standard fleet.
+    async fn suggest_board(
+        &self,
+        fleet: Option<Vec<u8>>,
+        seed: Option<u64>,
+    ) -> Result<Vec<ShipPlacementInput>, String> {
+        let fleet = fleet
+            .or_else(|| self.room.as_ref().map(|r| r.settings.fleet.clone()))
+            .filter(|fleet| !fleet.is_empty())
+            .unwrap_or_else(|| STANDARD_FLEET.to_vec());
+        let seed = seed.unwrap_or_else(|| chain_seed(&self.chain_id, self.block_height));
+        match &self.room {
+            Some(room) => random_board_placements(BOARD_SIZE, &fleet, &room.settings, seed),
+            None => generate_random_board(BOARD_SIZE, &fleet, seed),
         }
+    }
+
+    /// Shot hints against one opponent (the first by default). Unless a `fleet` is given, assumes the ships
+    /// of the room's fleet (or the standard fleet) that have not been sunk yet.
+    async fn target_heatmap(
+        &self,
+        chain_id: Option<String>,
+        fleet: Option<Vec<u8>>,
+    ) -> Result<Option<TargetHeatmap>, String> {
+        let rule = match &self.room {
+            Some(room) if room.game_state == GameState::InGame && !room.settings.target_hints => {
+                return Err("Hints are disabled in this room".into());
+            }
+            Some(room) => room.settings.placement_rule,
+            None => PlacementRule::default(),
+        };
+        let view = match chain_id {
+            Some(chain_id) => self
+                .enemy_views
+                .iter()
+                .find(|v| v.owner_chain_id == chain_id),
+            None => self.enemy_views.first(),
+        };
+        Ok(view.map(|view| {
+            let fleet = fleet.clone().unwrap_or_else(|| {
+                let room_fleet = self
+                    .room
+                    .as_ref()
+                    .map(|r| r.settings.fleet.as_slice())
+                    .unwrap_or_default();
+                let full = if room_fleet.is_empty() {
+                    &STANDARD_FLEET[..]
+                } else {
+                    room_fleet
+                };
+                fleet_remaining(full, &view.sunk_ships)
+            });
+            TargetHeatmap {
+                owner_chain_id: view.owner_chain_id.clone(),
+                size: view.size,
+                weights: target_heatmap(view, &fleet, rule),
+            }
+        }))
+    }
 
-        let ships = board
-            .ships
+    /// Sunk and remaining ship sizes of every opponent, as far as this chain has seen them.
+    async fn enemy_fleet_remaining(&self) -> Vec<EnemyFleetStatus> {
+        let Some(room) = self.room.as_ref() else {
+            return Vec::new();
+        };
+        self.enemy_views
             .iter()
-            .map(|s| ShipView {
-                id: s.id,
-                cells: s.cells.clone(),
-            })
-            .collect();
+            .map(|view| enemy_fleet_status(view, &room.settings))
+            .collect()
+    }
 
-        Some(MyBoardView { size, cells, ships })
+    async fn my_fleet_status(&self) -> Vec<ShipStatus> {
+        self.board.as_ref().map(fleet_status).unwrap_or_default()
+    }
+
+    async fn weapons_remaining(&self) -> Option<WeaponLoadout> {
+        let room = self.room.as_ref()?;
+        Some(battleship_game::weapons_remaining(room, &self.chain_id))
     }
 
     async fn last_reveal(&self) -> Option<&RevealInfo> {
@@ -169,6 +294,81 @@ impl QueryRoot {
     async fn room_invitations(&self) -> Vec<Invitation> {
         self.room_invitations.clone()
     }
+
+    async fn saved_layouts(&self) -> &Vec<SavedLayout> {
+        &self.saved_layouts
+    }
+
+    /// Official orchestrator chains from the application parameters; empty if any chain may orchestrate.
+    async fn orchestrators(&self) -> Vec<String> {
+        let parameters = &self.parameters;
+        let shards = parameters.matchmaking_shards.iter().map(|s| &s.chain_id);
+        let mut chain_ids: Vec<String> = Vec::new();
+        for chain_id in parameters.orchestrator_chain_ids.iter().chain(shards) {
+            if !chain_ids.contains(&chain_id.to_string()) {
+                chain_ids.push(chain_id.to_string());
+            }
+        }
+        chain_ids
+    }
+
+    /// The matchmaking shard `searchPlayer` uses for a region and game mode, if shards are configured.
+    async fn matchmaking_shard(
+        &self,
+        region: Option<String>,
+        game_mode: Option<GameMode>,
+    ) -> Option<String> {
+        self.parameters
+            .shard_for(region.as_deref(), game_mode.unwrap_or_default())
+            .map(|c| c.to_string())
+    }
+
+    /// This chain's place in the matchmaking queue while searching.
+    async fn matchmaking_status(&self) -> Option<&QueueStatus> {
+        self.matchmaking_status.as_ref()
+    }
+
+    /// Queue of this chain, when it acts as orchestrator.
+    async fn matchmaking_queue(&self) -> MatchmakingQueueStats {
+        queue_stats(&self.matchmaking_queue, &self.recent_match_waits)
+    }
+
+    /// Open rooms published to this chain's lobby, when it acts as orchestrator.
+    async fn open_rooms(&self, filter: Option<LobbyFilter>) -> Vec<RoomListing> {
+        let filter = filter.unwrap_or_default();
+        self.open_rooms
+            .iter()
+            .filter(|listing| listing_matches(listing, &filter))
+            .cloned()
+            .collect()
+    }
+
+    /// Tournament organized by this chain, with its bracket in `matches`.
+    async fn tournament(&self) -> Option<&Tournament> {
+        self.tournament.as_ref()
+    }
+
+    async fn tournament_standings(&self) -> Vec<TournamentPlayer> {
+        self.tournament
+            .as_ref()
+            .map(tournament_standings)
+            .unwrap_or_default()
+    }
+
+    /// Organizer of the tournament this chain registered for.
+    async fn tournament_organizer(&self) -> Option<&String> {
+        self.tournament_organizer.as_ref()
+    }
+
+    /// Tournament match hosted here, until its result is reported.
+    async fn tournament_match(&self) -> Option<&TournamentMatchRef> {
+        self.tournament_match.as_ref()
+    }
+
+    /// Name the chain plays under when it runs as a bot opponent.
+    async fn bot_name(&self) -> Option<String> {
+        self.bot_name.clone()
+    }
 }
 
 struct MutationRoot {
@@ -177,30 +377,184 @@ struct MutationRoot {
 
 #[Object]
 impl MutationRoot {
-    async fn create_room(&self, host_name: String) -> String {
-        self.runtime
-            .schedule_operation(&Operation::CreateRoom { host_name: host_name.clone() });
+    async fn create_room(
+        &self,
+        host_name: String,
+        settings: Option<RoomSettings>,
+        join_code: Option<String>,
+    ) -> String {
+        self.runtime.schedule_operation(&Operation::CreateRoom {
+            host_name: host_name.clone(),
+            settings: settings.unwrap_or_default(),
+            join_code,
+        });
         format!("Room created by '{}'", host_name)
     }
 
-    async fn join_room(&self, host_chain_id: String, player_name: String) -> String {
+    async fn join_room(
+        &self,
+        host_chain_id: String,
+        player_name: String,
+        join_code: Option<String>,
+        stake: Option<Amount>,
+    ) -> String {
         self.runtime.schedule_operation(&Operation::JoinRoom {
             host_chain_id: host_chain_id.clone(),
             player_name: player_name.clone(),
+            join_code,
+            stake: stake.unwrap_or_default(),
         });
         format!("Join request sent to {}", host_chain_id)
     }
 
-    async fn search_player(&self, orchestrator_chain_id: String, player_name: String) -> String {
+    async fn search_player(
+        &self,
+        orchestrator_chain_id: Option<String>,
+        player_name: String,
+        preferences: Option<MatchPreferences>,
+        region: Option<String>,
+    ) -> String {
+        let message = match &orchestrator_chain_id {
+            Some(orchestrator_chain_id) => {
+                format!("Search requested via {}", orchestrator_chain_id)
+            }
+            None => "Search requested via the matchmaking shard".to_string(),
+        };
+        self.runtime.schedule_operation(&Operation::SearchPlayer {
+            orchestrator_chain_id,
+            player_name,
+            preferences: preferences.unwrap_or_default(),
+            region,
+        });
+        message
+    }
+
+    async fn submit_board(
+        &self,
+        ships: Vec<ShipPlacementInput>,
+        shapes: Option<Vec<ShapePlacementInput>>,
+        mines: Option<Vec<Coord>>,
+    ) -> String {
+        self.runtime.schedule_operation(&Operation::SubmitBoard {
+            ships,
+            shapes: shapes.unwrap_or_default(),
+            mines: mines.unwrap_or_default(),
+        });
+        "Board submitted".to_string()
+    }
+
+    async fn save_layout(&self, name: String, ships: Vec<ShipPlacementInput>) -> String {
+        self.runtime.schedule_operation(&Operation::SaveLayout {
+            name: name.clone(),
+            ships,
+        });
+        format!("Layout '{}' saved", name)
+    }
+
+    async fn delete_layout(&self, name: String) -> String {
+        self.runtime
+            .schedule_operation(&Operation::DeleteLayout { name: name.clone() });
+        format!("Layout '{}' deleted", name)
+    }
+
+    async fn publish_room(
+        &self,
+        orchestrator_chain_id: Option<String>,
+        room_name: String,
+    ) -> String {
+        self.runtime.schedule_operation(&Operation::PublishRoom {
+            orchestrator_chain_id,
+            room_name: room_name.clone(),
+        });
+        format!("Room '{}' published", room_name)
+    }
+
+    async fn unpublish_room(&self) -> String {
+        self.runtime.schedule_operation(&Operation::UnpublishRoom);
+        "Room unpublished".to_string()
+    }
+
+    async fn claim_timeout(&self) -> String {
+        self.runtime.schedule_operation(&Operation::ClaimTimeout);
+        "Timeout claimed".to_string()
+    }
+
+    async fn offer_draw(&self) -> String {
+        self.runtime.schedule_operation(&Operation::OfferDraw);
+        "Draw offered".to_string()
+    }
+
+    async fn accept_draw(&self) -> String {
+        self.runtime.schedule_operation(&Operation::AcceptDraw);
+        "Draw accepted".to_string()
+    }
+
+    async fn propose_abort(&self) -> String {
+        self.runtime.schedule_operation(&Operation::ProposeAbort);
+        "Abort proposed".to_string()
+    }
+
+    async fn accept_abort(&self) -> String {
+        self.runtime.schedule_operation(&Operation::AcceptAbort);
+        "Abort accepted".to_string()
+    }
+
+    async fn rebalance_matchmaking(&self) -> String {
         self.runtime
-            .schedule_operation(&Operation::SearchPlayer { orchestrator_chain_id: orchestrator_chain_id.clone(), player_name });
-        format!("Search requested via {}", orchestrator_chain_id)
+            .schedule_operation(&Operation::RebalanceMatchmaking);
+        "Matchmaking rebalance requested".to_string()
     }
 
-    async fn submit_board(&self, ships: Vec<ShipPlacementInput>) -> String {
+    async fn create_tournament(
+        &self,
+        name: String,
+        format: Option<TournamentFormat>,
+        settings: Option<RoomSettings>,
+        max_players: Option<u32>,
+        rounds: Option<u32>,
+    ) -> String {
         self.runtime
-            .schedule_operation(&Operation::SubmitBoard { ships });
-        "Board submitted".to_string()
+            .schedule_operation(&Operation::CreateTournament {
+                name: name.clone(),
+                format: format.unwrap_or_default(),
+                settings: settings.unwrap_or_default(),
+                max_players: max_players.unwrap_or(8),
+                rounds,
+            });
+        format!("Tournament '{}' created", name)
+    }
+
+    async fn start_tournament(&self) -> String {
+        self.runtime.schedule_operation(&Operation::StartTournament);
+        "Tournament start requested".to_string()
+    }
+
+    async fn decide_tournament_match(
+        &self,
+        host_chain_id: String,
+        winner_chain_id: String,
+    ) -> String {
+        self.runtime
+            .schedule_operation(&Operation::DecideTournamentMatch {
+                host_chain_id,
+                winner_chain_id: winner_chain_id.clone(),
+            });
+        format!("Match decided for {}", winner_chain_id)
+    }
+
+    async fn register_tournament(&self, organizer_chain_id: String, player_name: String) -> String {
+        self.runtime
+            .schedule_operation(&Operation::RegisterTournament {
+                organizer_chain_id: organizer_chain_id.clone(),
+                player_name,
+            });
+        format!("Registration sent to {}", organizer_chain_id)
+    }
+
+    async fn submit_saved_layout(&self, name: String) -> String {
+        self.runtime
+            .schedule_operation(&Operation::SubmitSavedLayout { name: name.clone() });
+        format!("Layout '{}' submitted", name)
     }
 
     async fn start_game(&self) -> String {
@@ -208,17 +562,46 @@ impl MutationRoot {
         "Start game requested".to_string()
     }
 
-    async fn attack(&self, row: i32, col: i32) -> String {
+    async fn attack(&self, row: i32, col: i32, target_chain_id: Option<String>) -> String {
         if row < 0 || col < 0 || row > 255 || col > 255 {
             return "Invalid coords".to_string();
         }
         self.runtime.schedule_operation(&Operation::Attack {
             row: row as u8,
             col: col as u8,
+            target_chain_id,
         });
         format!("Attack sent: ({},{})", row, col)
     }
 
+    async fn salvo(&self, shots: Vec<Coord>, target_chain_id: Option<String>) -> String {
+        let count = shots.len();
+        self.runtime.schedule_operation(&Operation::Salvo {
+            shots,
+            target_chain_id,
+        });
+        format!("Salvo sent: {} shots", count)
+    }
+
+    async fn use_weapon(
+        &self,
+        weapon: Weapon,
+        row: i32,
+        col: i32,
+        target_chain_id: Option<String>,
+    ) -> String {
+        if row < 0 || col < 0 || row > 255 || col > 255 {
+            return "Invalid coords".to_string();
+        }
+        self.runtime.schedule_operation(&Operation::UseWeapon {
+            weapon,
+            row: row as u8,
+            col: col as u8,
+            target_chain_id,
+        });
+        format!("{:?} sent: ({},{})", weapon, row, col)
+    }
+
     async fn leave_room(&self) -> String {
         self.runtime.schedule_operation(&Operation::LeaveRoom);
         "Leave requested".to_string()
@@ -248,10 +631,16 @@ impl MutationRoot {
         format!("Invitation sent to '{}'", friend_chain_id)
     }
 
-    async fn accept_invite(&self, host_chain_id: String, player_name: String) -> String {
+    async fn accept_invite(
+        &self,
+        host_chain_id: String,
+        player_name: String,
+        stake: Option<Amount>,
+    ) -> String {
         self.runtime.schedule_operation(&Operation::AcceptInvite {
             host_chain_id: host_chain_id.clone(),
             player_name,
+            stake: stake.unwrap_or_default(),
         });
         format!("Invitation from '{}' accepted", host_chain_id)
     }
@@ -261,4 +650,44 @@ impl MutationRoot {
             .schedule_operation(&Operation::DeclineInvite { host_chain_id: host_chain_id.clone() });
         format!("Invitation from '{}' declined", host_chain_id)
     }
+
+    async fn set_bot_mode(&self, bot_name: Option<String>) -> String {
+        let message = match &bot_name {
+            Some(name) => format!("Bot mode enabled as '{}'", name),
+            None => "Bot mode disabled".to_string(),
+        };
+        self.runtime
+            .schedule_operation(&Operation::SetBotMode { bot_name });
+        message
+    }
+}
+
+fn board_view(board: &Board) -> MyBoardView {
+    let size = board.size;
+    let mut cells = Vec::with_capacity(board.cells.len());
+    for row in 0..size {
+        for col in 0..size {
+            let idx = (row as usize) * (size as usize) + (col as usize);
+            if let Some(cell) = board.cells.get(idx) {
+                cells.push(MyCellView {
+                    row,
+                    col,
+                    ship_id: cell.ship_id,
+                    attacked: cell.attacked,
+                    terrain: cell.terrain,
+                });
+            }
+        }
+    }
+
+    let ships = board
+        .ships
+        .iter()
+        .map(|s| ShipView {
+            id: s.id,
+            cells: s.cells.clone(),
+        })
+        .collect();
+
+    MyBoardView { size, cells, ships }
 }
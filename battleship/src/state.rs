@@ -1,13 +1,21 @@
-use battleship_game::{Board, EnemyBoardView, Invitation, MatchmakingPlayer, RevealInfo, Room};
-use linera_sdk::views::{linera_views, RegisterView, RootView, ViewStorageContext};
+use battleship_game::{
+    Board, EnemyBoardView, Invitation, MatchmakingPlayer, PublishedRoom, QueueStatus, RevealInfo,
+    Room, RoomListing, SavedLayout, Tournament, TournamentMatchRef,
+};
+use linera_sdk::{
+    linera_base_types::CryptoHash,
+    views::{linera_views, RegisterView, RootView, ViewStorageContext},
+};
 
 #[derive(RootView)]
 #[view(context = ViewStorageContext)]
 pub struct BattleshipState {
     pub room: RegisterView<Option<Room>>,
     pub board: RegisterView<Option<Board>>,
-    pub enemy_view: RegisterView<Option<EnemyBoardView>>,
+    pub teammate_board: RegisterView<Option<Board>>,
+    pub enemy_views: RegisterView<Vec<EnemyBoardView>>,
     pub subscribed_to_host: RegisterView<Option<String>>,
+    pub pending_join: RegisterView<Option<String>>,
     pub last_reveal: RegisterView<Option<RevealInfo>>,
     pub last_notification: RegisterView<Option<String>>,
     pub friends: RegisterView<Vec<String>>,
@@ -16,4 +24,19 @@ pub struct BattleshipState {
     pub room_invitations: RegisterView<Vec<Invitation>>,
     pub sent_invitations: RegisterView<Vec<String>>,
     pub matchmaking_queue: RegisterView<Vec<MatchmakingPlayer>>,
+    pub bot_name: RegisterView<Option<String>>,
+    pub saved_layouts: RegisterView<Vec<SavedLayout>>,
+    /// Salted hash of the join code of the room hosted here, if it is private.
+    pub join_code_hash: RegisterView<Option<CryptoHash>>,
+    pub published_room: RegisterView<Option<PublishedRoom>>,
+    /// Lobby of open rooms, on orchestrator chains.
+    pub open_rooms: RegisterView<Vec<RoomListing>>,
+    /// Waits (in microseconds) of the latest matches, on orchestrator chains.
+    pub recent_match_waits: RegisterView<Vec<u64>>,
+    pub matchmaking_status: RegisterView<Option<QueueStatus>>,
+    /// Tournament organized by this chain.
+    pub tournament: RegisterView<Option<Tournament>>,
+    /// Organizer of the tournament this chain registered for.
+    pub tournament_organizer: RegisterView<Option<String>>,
+    pub tournament_match: RegisterView<Option<TournamentMatchRef>>,
 }